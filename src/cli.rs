@@ -1,6 +1,10 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use clap_stdin::MaybeStdin;
 
+use regex::{Regex, RegexBuilder};
+
+use crate::{client::cluster::ClusterSource, config::ContextConfig, language::Language, progress::ProgressFormat};
+
 
 #[derive(Parser, Debug)]
 #[command(name = "brichka")]
@@ -14,6 +18,10 @@ pub struct Cli {
     #[arg(long, global = true)]
     pub debug: bool,
 
+    /// Format of the progress reported on stderr while waiting for clusters, warehouses, Spark jobs and downloads
+    #[arg(long, global = true, value_enum, default_value_t = ProgressFormat::Text)]
+    pub progress: ProgressFormat,
+
     #[command(subcommand)]
     pub command: Commands,
 
@@ -27,6 +35,11 @@ pub enum Commands {
         #[command(subcommand)]
         command: ClusterCommands,
     },
+    /// SQL warehouse commands
+    Warehouse {
+        #[command(subcommand)]
+        command: WarehouseCommands,
+    },
     /// Config commands
     Config {
         #[command(subcommand)]
@@ -36,25 +49,35 @@ pub enum Commands {
         global: bool,
     },
     /// Initialize a new execution context in the current working directory
-    Init,
+    Init {
+        /// Name of the execution context
+        #[arg(long, short, default_value = ContextConfig::DEFAULT_NAME)]
+        name: String,
+        /// Language of the execution context, used by `brichka run` when the language of a cell can not be inferred
+        #[arg(long, short, value_enum, default_value_t = Language::Sql)]
+        language: Language,
+    },
+    /// Execution context commands
+    Context {
+        #[command(subcommand)]
+        command: ContextCommands,
+    },
     /// Status commands
     Status {
         #[command(subcommand)]
         command: StatusCommands,
     },
-    /// Run code on the interactive cluster
-    Run {
-        /// Code that will be executed on the interactive cluster. Pass `-` to read from stdin
-        command: MaybeStdin<String>,
-        /// `sql`, `scala`, `python` or `r`
-        #[arg(short, long)]
-        language: String,
-        /// If set brichka will automatically initialize a new shared execution context if the existing one does not exist or is not available anymore. If not set and no shared execution context can be found brichka will create a temporary one-off one
-        #[arg(long, short)]
-        init: bool,
-        /// Automatically start a terminated cluster
-        #[arg(long, short)]
-        start: bool,
+    /// Run code on the interactive cluster or a SQL warehouse
+    Run(RunArgs),
+    /// History of the commands executed with `brichka run`
+    History {
+        #[command(subcommand)]
+        command: HistoryCommands,
+    },
+    /// Scala dependency commands
+    Deps {
+        #[command(subcommand)]
+        command: DepsCommands,
     },
     /// Start LSP server for Unity Catalog completion
     Lsp,
@@ -62,20 +85,330 @@ pub enum Commands {
     Version
 }
 
+#[derive(Args, Debug, Clone)]
+pub struct RunArgs {
+    /// Code that will be executed on the interactive cluster. Pass `-` to read from stdin
+    #[arg(required_unless_present = "file")]
+    pub command: Option<MaybeStdin<String>>,
+    /// Read the code from a file instead
+    #[arg(long, short, conflicts_with = "command")]
+    pub file: Option<String>,
+    /// Language of the code. Can be omitted if it can be inferred from the `--file` extension or a magic command (`%sql`, `%python`, `%scala`, `%r`) on the first line. Takes precedence over both. Falls back to the language of the shared execution context
+    #[arg(short, long)]
+    pub language: Option<Language>,
+    /// If set brichka will automatically initialize a new shared execution context if the existing one does not exist or is not available anymore. If not set and no shared execution context can be found brichka will create a temporary one-off one
+    #[arg(long, short)]
+    pub init: bool,
+    /// When the shared execution context has to be initialized again, replay the cells previously executed in it first. Requires `--init`
+    #[arg(long, requires = "init")]
+    pub replay: bool,
+    /// Name of the shared execution context created with `brichka init --name`. Uses the default context if not set
+    #[arg(long, short)]
+    pub context: Option<String>,
+    /// Automatically start a terminated cluster
+    #[arg(long, short)]
+    pub start: bool,
+    /// Inline Scala files referenced with `//> using file`. Each file is sent only once per shared execution context
+    #[arg(long)]
+    pub using_files: bool,
+    /// Set a template variable, substituted for `${name}` and for `:name` in SQL. Can be repeated. Overrides values from `.brichka/vars.json` and `brichka: var` defaults
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+    /// Run the SQL code on the configured SQL warehouse instead of the interactive cluster
+    #[arg(long, short, conflicts_with_all = ["init", "start", "context", "using_files"])]
+    pub warehouse: bool,
+    /// Fetch large warehouse results as chunks from cloud storage instead of inline. Use for results over 25 MiB
+    #[arg(long, requires = "warehouse")]
+    pub external_links: bool,
+    /// Maximum number of result chunks downloaded at the same time with `--external-links`
+    #[arg(long, requires = "external_links", default_value_t = RunArgs::DEFAULT_PARALLEL_DOWNLOADS)]
+    pub parallel_downloads: usize,
+}
+
+impl RunArgs {
+    pub const DEFAULT_PARALLEL_DOWNLOADS: usize = 4;
+}
+
 #[derive(Subcommand, Debug, Clone)]
-pub enum ClusterCommands {
-    /// Prints a list of clusters with their id and current state
+pub enum DepsCommands {
+    /// Install the Maven dependencies declared with `//> using dep` in Scala files that are missing on the selected cluster.
+    /// Spark itself and dependencies in excluded code are skipped
+    Sync {
+        /// Scala files to read the dependencies from. Defaults to every `.sc` and `.scala` file in the working directory
+        files: Vec<String>,
+        /// Only print the declared and missing dependencies without installing them
+        #[arg(long)]
+        dry_run: bool,
+        /// Wait until the missing dependencies are installed. The cluster must be running
+        #[arg(long, short)]
+        wait: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum LibraryCommands {
+    /// Print the libraries of the selected cluster with their status as JSON
     List,
-    /// Start a terminated cluster
-    Start,
+    /// Install libraries on the selected cluster
+    Install {
+        #[command(flatten)]
+        libraries: LibraryArgs,
+        /// Maven or PyPI repository to install `--maven` and `--pypi` libraries from
+        #[arg(long)]
+        repo: Option<String>,
+        /// Wait until every library is installed. The cluster must be running
+        #[arg(long, short)]
+        wait: bool,
+    },
+    /// Uninstall libraries from the selected cluster. They are removed when the cluster restarts
+    Uninstall {
+        #[command(flatten)]
+        libraries: LibraryArgs,
+        /// Repository the `--maven` and `--pypi` libraries were installed from
+        #[arg(long)]
+        repo: Option<String>,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+#[group(required = true, multiple = true)]
+pub struct LibraryArgs {
+    /// Maven coordinates, e.g. `com.example:library_2.12:1.0.0`. Can be repeated
+    #[arg(long)]
+    pub maven: Vec<String>,
+    /// PyPI package, optionally with a version, e.g. `requests==2.32.0`. Can be repeated
+    #[arg(long)]
+    pub pypi: Vec<String>,
+    /// Path of a jar, e.g. in a Volume (`/Volumes/catalog/schema/volume/library.jar`). Can be repeated
+    #[arg(long)]
+    pub jar: Vec<String>,
+    /// Path of a Python wheel, e.g. in a Volume. Can be repeated
+    #[arg(long)]
+    pub whl: Vec<String>,
+}
+
+/// Driver log file read by `brichka cluster logs`, stdout by default
+#[derive(Args, Debug, Clone)]
+#[group(multiple = false)]
+pub struct LogFileArgs {
+    /// Standard output of the driver, e.g. `println` output
+    #[arg(long)]
+    pub stdout: bool,
+    /// Standard error of the driver
+    #[arg(long)]
+    pub stderr: bool,
+    /// Log4j log of the driver, including failed tasks and lost executors
+    #[arg(long)]
+    pub log4j: bool,
+}
+
+impl LogFileArgs {
+    pub fn file_name(&self) -> &'static str {
+        if self.stderr {
+            "stderr"
+        } else if self.log4j {
+            "log4j-active.log"
+        } else {
+            "stdout"
+        }
+    }
+}
+
+/// Filters for the clusters shown by `brichka cluster list` and `brichka config cluster`
+#[derive(Args, Debug, Clone)]
+pub struct ClusterFilter {
+    /// Only show clusters created by these sources. Can be repeated
+    #[arg(long = "source", value_enum, default_values_t = [ClusterSource::Ui])]
+    pub sources: Vec<ClusterSource>,
+    /// Only show clusters in these states, e.g. `running` or `terminated`. Can be repeated
+    #[arg(long = "state", value_parser = parse_uppercase)]
+    pub states: Vec<String>,
+    /// Only show clusters whose name matches this case-insensitive regular expression
+    #[arg(long, value_parser = parse_name_pattern)]
+    pub name: Option<NamePattern>,
+    /// Only show clusters created by this user
+    #[arg(long)]
+    pub owner: Option<String>,
+    /// Only show clusters with this custom tag, given as `KEY` or `KEY=VALUE`. Can be repeated
+    #[arg(long = "tag", value_parser = parse_tag)]
+    pub tags: Vec<(String, Option<String>)>,
+}
+
+/// A cluster name as typed, matched exactly or as a case-insensitive regular expression
+#[derive(Debug, Clone)]
+pub struct NamePattern {
+    pub literal: String,
+    /// `None` if the name is not a valid regular expression, it is then only matched exactly
+    regex: Option<Regex>,
+}
+
+impl NamePattern {
+    pub fn new(value: &str) -> NamePattern {
+        let regex = RegexBuilder::new(value).case_insensitive(true).build().ok();
+        NamePattern { literal: value.to_string(), regex }
+    }
+
+    pub fn is_exact_match(&self, name: &str) -> bool {
+        name.eq_ignore_ascii_case(&self.literal)
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        self.is_exact_match(name) || self.regex.as_ref().is_some_and(|regex| regex.is_match(name))
+    }
+}
+
+fn parse_name_pattern(value: &str) -> Result<NamePattern, String> {
+    Ok(NamePattern::new(value))
+}
+
+fn parse_uppercase(value: &str) -> Result<String, String> {
+    Ok(value.to_uppercase())
+}
+
+/// Parses durations like `45s`, `30m`, `2h` or `7d` into seconds
+fn parse_duration(value: &str) -> Result<u64, String> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount = amount.parse::<u64>().map_err(|_| format!("Invalid duration `{}`", value))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration `{}`, use a number followed by `s`, `m`, `h` or `d`", value)),
+    };
+    amount.checked_mul(multiplier).ok_or_else(|| format!("Duration `{}` is too long", value))
+}
+
+fn parse_tag(value: &str) -> Result<(String, Option<String>), String> {
+    match value.split_once('=') {
+        Some(("", _)) => Err("Tag key can not be empty".to_string()),
+        Some((key, value)) => Ok((key.to_string(), Some(value.to_string()))),
+        None => Ok((value.to_string(), None)),
+    }
+}
+
+fn parse_var(value: &str) -> Result<(String, String), String> {
+    crate::variables::parse_assignment(value).map_err(|e| e.to_string())
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ClusterCommands {
+    /// Prints a list of clusters with their id and current state, running clusters first
+    List(ClusterFilter),
+    /// Start a terminated cluster and wait until it is running
+    Start {
+        /// Stop waiting after this long, e.g. `10m`. The cluster keeps starting
+        #[arg(long, value_parser = parse_duration)]
+        timeout: Option<u64>,
+    },
+    /// Create a cluster from a template, wait until it is running and select it for the working directory
+    Create {
+        /// JSON file with a `/clusters/create` request and optional `libraries`. `{branch}` is replaced with the current git branch
+        #[arg(long, default_value = ".brichka/cluster-template.json")]
+        from: String,
+        /// Name of the cluster, overrides `cluster_name` of the template
+        #[arg(long, short)]
+        name: Option<String>,
+        /// Stop waiting after this long, e.g. `10m`. The cluster keeps starting
+        #[arg(long, value_parser = parse_duration)]
+        timeout: Option<u64>,
+    },
+    /// Permanently delete the selected cluster
+    Destroy {
+        /// Also delete clusters that were not created with `brichka cluster create`
+        #[arg(long)]
+        force: bool,
+    },
+    /// Terminate the selected cluster
+    Stop {
+        /// Wait until the cluster is terminated
+        #[arg(long, short)]
+        wait: bool,
+    },
+    /// Restart the selected cluster. Every execution context on it is lost
+    Restart {
+        /// Wait until the cluster is running again
+        #[arg(long, short)]
+        wait: bool,
+    },
     /// Print the current status of the selected cluster
     Status,
+    /// Print the events of the selected cluster as JSON, most recent first
+    Events {
+        /// Only show events newer than this, e.g. `30m`, `2h` or `7d`
+        #[arg(long, value_parser = parse_duration)]
+        since: Option<u64>,
+        /// Only show events older than this, e.g. `30m`, `2h` or `7d`
+        #[arg(long, value_parser = parse_duration)]
+        until: Option<u64>,
+        /// Only show events of this type, e.g. `terminating` or `driver_not_responding`. Can be repeated
+        #[arg(long = "type", value_parser = parse_uppercase)]
+        event_types: Vec<String>,
+        /// Maximum number of events
+        #[arg(long, short = 'n', default_value_t = 50)]
+        limit: usize,
+    },
+    /// Manage the libraries installed on the selected cluster
+    Libraries {
+        #[command(subcommand)]
+        command: LibraryCommands,
+    },
+    /// Print the runtime version, node types, workers, tags and Spark UI URL of the selected cluster as JSON
+    Info {
+        /// Print a human readable summary instead of JSON
+        #[arg(long, short)]
+        pretty: bool,
+    },
+    /// Print the driver logs of the selected cluster from its log delivery location in DBFS or a Volume
+    Logs {
+        #[command(flatten)]
+        file: LogFileArgs,
+        /// Keep polling the log for new output
+        #[arg(long, short)]
+        follow: bool,
+        /// Number of lines printed from the end of the log
+        #[arg(long, short = 'n', default_value_t = 100)]
+        lines: usize,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum WarehouseCommands {
+    /// Prints a list of SQL warehouses with their id and current state
+    List,
+    /// Start a stopped SQL warehouse
+    Start {
+        /// Wait until the warehouse is running
+        #[arg(long, short)]
+        wait: bool,
+    },
+    /// Stop the selected SQL warehouse
+    Stop {
+        /// Wait until the warehouse is stopped
+        #[arg(long, short)]
+        wait: bool,
+    },
+    /// Print the current status of the selected SQL warehouse
+    Status,
 }
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum ConfigCommands {
-    /// Run fuzzy finder to select a cluser that will be used to run the code
-    Cluster,
+    /// Run fuzzy finder to select a cluser that will be used to run the code.
+    /// With `--id`, or `--name` matching a single cluster, the cluster is selected without the fuzzy finder
+    Cluster {
+        /// Select the cluster with this id
+        #[arg(long, conflicts_with = "name")]
+        id: Option<String>,
+        #[command(flatten)]
+        filter: ClusterFilter,
+        /// Hide single user clusters assigned to another user or group
+        #[arg(long)]
+        attachable_only: bool,
+    },
+    /// Run fuzzy finder to select a SQL warehouse that will be used to run SQL code with `brichka run --warehouse`
+    Warehouse,
     /// Configure Databricks authentication
     Auth {
         #[command(subcommand)]
@@ -83,10 +416,67 @@ pub enum ConfigCommands {
     }
 }
 
+#[derive(Subcommand, Debug, Clone)]
+pub enum HistoryCommands {
+    /// List recorded runs, oldest first
+    List {
+        /// Only list the most recent runs
+        #[arg(long, short = 'n')]
+        limit: Option<usize>,
+    },
+    /// Show the executed code and result of a run. Opens a picker if no id is given
+    Show {
+        id: Option<u64>,
+    },
+    /// Run the code of a previous run again. Opens a picker if no id is given
+    Rerun {
+        id: Option<u64>,
+        /// Automatically start a terminated cluster
+        #[arg(long, short)]
+        start: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ContextCommands {
+    /// Prints a list of execution contexts of the current working directory with their cluster and status
+    List,
+    /// Destroy an execution context on the cluster and remove it from the current working directory
+    Destroy {
+        /// Name of the execution context
+        #[arg(long, short, default_value = ContextConfig::DEFAULT_NAME)]
+        name: String,
+    },
+    /// Destroy execution contexts created by brichka that are not used anymore and print them
+    Gc,
+    /// Print the temporary views, dataframes and variables defined in an execution context
+    Inspect {
+        /// Name of the execution context
+        #[arg(long, short, default_value = ContextConfig::DEFAULT_NAME)]
+        name: String,
+        /// Languages to inspect. Can be repeated. Defaults to SQL and the language of the context
+        #[arg(long, short)]
+        language: Vec<Language>,
+    },
+    /// Create a new execution context and execute the cells recorded for the previous one, e.g. after a cluster restart
+    Replay {
+        /// Name of the execution context
+        #[arg(long, short, default_value = ContextConfig::DEFAULT_NAME)]
+        name: String,
+        /// Automatically start a terminated cluster
+        #[arg(long, short)]
+        start: bool,
+    },
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub enum StatusCommands {
     /// Show the current state of the shared execution context
-    Context,
+    Context {
+        /// Name of the execution context
+        #[arg(long, short, default_value = ContextConfig::DEFAULT_NAME)]
+        name: String,
+    },
     /// Show the current state of the selected cluster
     Cluster
 }
@@ -112,3 +502,34 @@ pub enum AuthConfigCommands {
         profile: String,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_names_literally_or_as_pattern() {
+        let pattern = parse_name_pattern("etl.dev (v2)").unwrap();
+        assert!(pattern.is_exact_match("ETL.dev (v2)"));
+        assert!(pattern.is_match("etl.dev (v2)"));
+        assert!(!pattern.is_match("etl-dev-v3"));
+
+        let pattern = parse_name_pattern("^etl-").unwrap();
+        assert!(pattern.is_match("ETL-prod"));
+        assert!(!pattern.is_exact_match("etl-prod"));
+
+        let pattern = parse_name_pattern("etl (dev").unwrap();
+        assert!(pattern.is_match("etl (dev"));
+        assert!(!pattern.is_match("etl dev"));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("45s"), Ok(45));
+        assert_eq!(parse_duration("30m"), Ok(30 * 60));
+        assert_eq!(parse_duration("7d"), Ok(7 * 24 * 60 * 60));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("99999999999999999d").is_err());
+    }
+}
@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
 use reqwest::Method;
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
@@ -5,7 +8,9 @@ use anyhow::Result;
 
 #[derive(Deserialize)]
 pub struct ListClustersResponse {
-    pub clusters: Vec<Cluster>
+    #[serde(default)]
+    pub clusters: Vec<Cluster>,
+    pub next_page_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -15,19 +20,123 @@ pub struct Cluster{
     #[serde(rename(deserialize  = "cluster_name" ))]
     pub name: String,
     pub state: String,
+    /// Only read for filtering, `cluster list` prints the id, name and state
+    #[serde(skip_serializing)]
+    pub creator_user_name: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub custom_tags: BTreeMap<String, String>,
+    #[serde(skip_serializing)]
+    pub data_security_mode: Option<String>,
+    #[serde(skip_serializing)]
+    pub single_user_name: Option<String>,
+}
+
+/// What created the cluster
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ClusterSource {
+    Ui,
+    Api,
+    Job,
+    Pipeline,
+    PipelineMaintenance,
+    Models,
+    Sql,
 }
 
+impl ClusterSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClusterSource::Ui => "UI",
+            ClusterSource::Api => "API",
+            ClusterSource::Job => "JOB",
+            ClusterSource::Pipeline => "PIPELINE",
+            ClusterSource::PipelineMaintenance => "PIPELINE_MAINTENANCE",
+            ClusterSource::Models => "MODELS",
+            ClusterSource::Sql => "SQL",
+        }
+    }
+}
 
+const LIST_PAGE_SIZE: usize = 100;
 
-pub async fn list() -> Result<ListClustersResponse> {
-    let response = crate::client::call_databricks_api::<ListClustersResponse>(Method::GET, "/api/2.0/clusters/list?filter_by.cluster_sources=UI", None).await?;
-    Ok(response)
+/// Lists the clusters created by any of `sources` and in any of `states`, following every result page
+pub async fn list(sources: &[ClusterSource], states: &[String]) -> Result<Vec<Cluster>> {
+    let mut filters = vec![("page_size", LIST_PAGE_SIZE.to_string())];
+    filters.extend(sources.iter().map(|source| ("filter_by.cluster_sources", source.as_str().to_string())));
+    filters.extend(states.iter().map(|state| ("filter_by.cluster_states", state.to_owned())));
+
+    let mut clusters = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut params = filters.clone();
+        if let Some(token) = &page_token {
+            params.push(("page_token", token.to_owned()));
+        }
+        let path = crate::client::with_query("/api/2.1/clusters/list", &params);
+        let response = crate::client::call_databricks_api::<ListClustersResponse>(Method::GET, &path, None).await?;
+        clusters.extend(response.clusters);
+
+        match response.next_page_token {
+            Some(token) if !token.is_empty() => page_token = Some(token),
+            _ => break,
+        }
+    }
+    Ok(clusters)
 }
 
 
-#[derive(Deserialize)]
+/// Cluster details. Driver and executor addresses are deliberately left out
+#[derive(Debug, Deserialize, Serialize)]
 pub struct GetClusterInfoResponse {
-    pub state: String
+    pub cluster_id: String,
+    pub cluster_name: Option<String>,
+    pub state: String,
+    pub state_message: Option<String>,
+    pub spark_version: Option<String>,
+    pub node_type_id: Option<String>,
+    pub driver_node_type_id: Option<String>,
+    /// Current number of workers, not used when the cluster autoscales
+    pub num_workers: Option<u64>,
+    pub autoscale: Option<Autoscale>,
+    pub creator_user_name: Option<String>,
+    pub data_security_mode: Option<String>,
+    pub single_user_name: Option<String>,
+    #[serde(default)]
+    pub custom_tags: BTreeMap<String, String>,
+    /// 0 when automatic termination is disabled
+    pub autotermination_minutes: Option<u64>,
+    /// Milliseconds since the Unix epoch
+    pub last_activity_time: Option<u64>,
+    pub termination_reason: Option<TerminationReason>,
+    pub cluster_log_conf: Option<ClusterLogConf>,
+}
+
+/// Where the cluster delivers its driver and executor logs, at most one destination is set
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClusterLogConf {
+    pub dbfs: Option<LogDestination>,
+    pub volumes: Option<LogDestination>,
+    pub s3: Option<LogDestination>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LogDestination {
+    pub destination: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Autoscale {
+    pub min_workers: u64,
+    pub max_workers: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TerminationReason {
+    pub code: Option<String>,
+    #[serde(rename = "type")]
+    pub tpe: Option<String>,
+    #[serde(default)]
+    pub parameters: BTreeMap<String, String>,
 }
 
 pub async fn get_info(cluster_id: String) -> Result<GetClusterInfoResponse> {
@@ -39,6 +148,66 @@ pub async fn get_info(cluster_id: String) -> Result<GetClusterInfoResponse> {
 pub async fn start(cluster_id: String) -> Result<()> {
     let request_body = format!("{{\"cluster_id\": \"{}\"}}", cluster_id);
     let path = "/api/2.1/clusters/start";
-    crate::client::call_databricks_api::<serde_json::Value>(Method::POST, path, Some(request_body)).await?;
-    Ok(())
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, path, Some(request_body)).await?;
+    crate::client::check_api_error(&response)
+}
+
+/// Terminates the cluster. It keeps its configuration and can be started again
+pub async fn stop(cluster_id: String) -> Result<()> {
+    let request_body = format!("{{\"cluster_id\": \"{}\"}}", cluster_id);
+    let path = "/api/2.1/clusters/delete";
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, path, Some(request_body)).await?;
+    crate::client::check_api_error(&response)
+}
+
+pub async fn restart(cluster_id: String) -> Result<()> {
+    let request_body = format!("{{\"cluster_id\": \"{}\"}}", cluster_id);
+    let path = "/api/2.1/clusters/restart";
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, path, Some(request_body)).await?;
+    crate::client::check_api_error(&response)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClusterEvent {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    #[serde(rename = "type")]
+    pub tpe: String,
+    #[serde(default)]
+    pub details: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+pub struct GetEventsResponse {
+    #[serde(default)]
+    pub events: Vec<ClusterEvent>,
+    /// Request body for the next page, absent on the last page
+    pub next_page: Option<serde_json::Value>,
+}
+
+/// Returns one page of cluster events. `request` is the body of the `/events` request,
+/// the `next_page` of the response can be passed as the request for the following page
+pub async fn get_events(request: serde_json::Value) -> Result<GetEventsResponse> {
+    let path = "/api/2.1/clusters/events";
+    let response = crate::client::call_databricks_api::<GetEventsResponse>(Method::POST, path, Some(request.to_string())).await?;
+    Ok(response)
+}
+
+/// Creates a cluster from a `/clusters/create` request and returns its id
+pub async fn create(spec: &serde_json::Value) -> Result<String> {
+    let path = "/api/2.1/clusters/create";
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, path, Some(spec.to_string())).await?;
+    crate::client::check_api_error(&response)?;
+    response.get("cluster_id")
+        .and_then(|id| id.as_str())
+        .map(str::to_string)
+        .ok_or(anyhow::anyhow!("Databricks did not return the id of the created cluster"))
+}
+
+/// Deletes the cluster and its configuration, it can not be started again
+pub async fn permanent_delete(cluster_id: String) -> Result<()> {
+    let request_body = format!("{{\"cluster_id\": \"{}\"}}", cluster_id);
+    let path = "/api/2.1/clusters/permanent-delete";
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, path, Some(request_body)).await?;
+    crate::client::check_api_error(&response)
 }
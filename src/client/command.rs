@@ -2,18 +2,20 @@ use reqwest::Method;
 use serde::Deserialize;
 use anyhow::Result;
 
+use crate::language::Language;
+
 
 #[derive(Deserialize)]
 pub struct RunCommandResponse {
     pub id: String
 }
 
-pub async fn run(command: String, cluster_id: String, context_id: String, language: String) -> Result<RunCommandResponse> {
+pub async fn run(command: String, cluster_id: String, context_id: String, language: Language) -> Result<RunCommandResponse> {
     let request_body = serde_json::json!({
         "clusterId": cluster_id,
         "contextId": context_id,
         "command": command,
-        "language": language
+        "language": language.as_str()
     });
 
     let response = crate::client::call_databricks_api::<RunCommandResponse>(Method::POST, "/api/1.2/commands/execute", Some(request_body.to_string())).await?;
@@ -2,17 +2,20 @@ use reqwest::Method;
 use serde::Deserialize;
 use anyhow::Result;
 
+use crate::language::Language;
+
 
 #[derive(Deserialize)]
 pub struct CreateContextResponse {
     pub id: String
 }
 
-pub async fn create(cluster_id: String, language: String) -> Result<CreateContextResponse> {
+pub async fn create(cluster_id: String, language: Language) -> Result<CreateContextResponse> {
     let request_body = format!("{{\"clusterId\": \"{}\", \"language\": \"{}\"}}", cluster_id, language);
 
-    let response = crate::client::call_databricks_api::<CreateContextResponse>(Method::POST, "/api/1.2/contexts/create", Some(request_body)).await?;
-    Ok(response)
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, "/api/1.2/contexts/create", Some(request_body)).await?;
+    crate::client::check_api_error(&response)?;
+    Ok(serde_json::from_value(response)?)
 }
 
 #[derive(Deserialize)]
@@ -25,3 +28,24 @@ pub async fn get_status(cluster_id: String, context_id: String) -> Result<GetCon
     let response = crate::client::call_databricks_api::<GetContextStatusResponse>(Method::GET, &path, None).await?;
     Ok(response)
 }
+
+pub async fn destroy(cluster_id: String, context_id: String) -> Result<()> {
+    let request_body = format!("{{\"clusterId\": \"{}\", \"contextId\": \"{}\"}}", cluster_id, context_id);
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, "/api/1.2/contexts/destroy", Some(request_body)).await?;
+    crate::client::check_api_error(&response)
+}
+
+/// Whether the context still exists. Contexts are gone once their cluster was restarted or terminated
+pub async fn exists(cluster_id: String, context_id: String) -> Result<bool> {
+    let path = format!("/api/1.2/contexts/status?clusterId={}&contextId={}", cluster_id, context_id);
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::GET, &path, None).await?;
+    if response.get("status").is_some() {
+        return Ok(true);
+    }
+    // Unknown contexts and clusters that are not running are reported with an `error` message, other failures with an `error_code`
+    if response.get("error_code").is_none() && response.get("error").is_some() {
+        return Ok(false);
+    }
+    crate::client::check_api_error(&response)?;
+    anyhow::bail!("Unexpected execution context status response")
+}
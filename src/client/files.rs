@@ -0,0 +1,97 @@
+use base64::Engine;
+use reqwest::{Method, header};
+use serde::Deserialize;
+use anyhow::{Context, Result};
+
+/// Maximum number of bytes returned by a single DBFS read
+pub const MAX_READ_BYTES: u64 = 1024 * 1024;
+
+/// A file in DBFS or in a Unity Catalog Volume
+pub enum RemoteFile {
+    /// Absolute DBFS path without the `dbfs:` scheme
+    Dbfs(String),
+    /// Path starting with `/Volumes/`
+    Volume(String),
+}
+
+#[derive(Deserialize)]
+struct DbfsFileStatus {
+    file_size: u64,
+    modification_time: Option<i64>,
+}
+
+/// Size and last modification of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStatus {
+    pub size: u64,
+    /// Opaque, only compared with a previous status of the same file
+    pub modified: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DbfsReadResponse {
+    /// Base64 encoded content
+    data: String,
+}
+
+impl RemoteFile {
+    pub fn path(&self) -> &str {
+        match self {
+            RemoteFile::Dbfs(path) | RemoteFile::Volume(path) => path,
+        }
+    }
+
+    pub async fn status(&self) -> Result<FileStatus> {
+        match self {
+            RemoteFile::Dbfs(path) => {
+                let api_path = crate::client::with_query("/api/2.0/dbfs/get-status", &[("path", path.to_owned())]);
+                let response = crate::client::call_databricks_api::<serde_json::Value>(Method::GET, &api_path, None).await?;
+                crate::client::check_api_error(&response)?;
+                let status = serde_json::from_value::<DbfsFileStatus>(response).context("Failed to parse DBFS file status")?;
+                Ok(FileStatus { size: status.file_size, modified: status.modification_time.map(|time| time.to_string()) })
+            },
+            RemoteFile::Volume(path) => {
+                let response = crate::client::databricks_request(Method::HEAD, &format!("/api/2.0/fs/files{}", path)).await?.send().await?;
+                if !response.status().is_success() {
+                    anyhow::bail!("Failed to get the size of `{}`: {}", path, response.status());
+                }
+                let header_value = |name| response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+                let size = header_value(header::CONTENT_LENGTH)
+                    .and_then(|length| length.parse::<u64>().ok())
+                    .context("Files API did not return the size of the file")?;
+                Ok(FileStatus { size, modified: header_value(header::LAST_MODIFIED) })
+            },
+        }
+    }
+
+    /// Reads up to `length` bytes starting at `offset`, at most `MAX_READ_BYTES`
+    pub async fn read(&self, offset: u64, length: u64) -> Result<Vec<u8>> {
+        let length = length.min(MAX_READ_BYTES);
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        match self {
+            RemoteFile::Dbfs(path) => {
+                let api_path = crate::client::with_query(
+                    "/api/2.0/dbfs/read",
+                    &[("path", path.to_owned()), ("offset", offset.to_string()), ("length", length.to_string())],
+                );
+                let response = crate::client::call_databricks_api::<serde_json::Value>(Method::GET, &api_path, None).await?;
+                crate::client::check_api_error(&response)?;
+                let data = serde_json::from_value::<DbfsReadResponse>(response).context("Failed to parse DBFS read response")?.data;
+                base64::engine::general_purpose::STANDARD.decode(data).context("Failed to decode DBFS file content")
+            },
+            RemoteFile::Volume(path) => {
+                let range = format!("bytes={}-{}", offset, offset + length - 1);
+                let response = crate::client::databricks_request(Method::GET, &format!("/api/2.0/fs/files{}", path)).await?
+                    .header(header::RANGE, range)
+                    .send().await?;
+                if !response.status().is_success() {
+                    anyhow::bail!("Failed to read `{}`: {}", path, response.status());
+                }
+                Ok(response.bytes().await?.to_vec())
+            },
+        }
+    }
+}
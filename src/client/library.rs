@@ -0,0 +1,64 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+
+
+/// A library installed on a cluster. Serialized in the shape the Libraries API expects, e.g. `{"pypi": {"package": "..."}}`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Library {
+    Jar(String),
+    Whl(String),
+    Maven {
+        coordinates: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        repo: Option<String>,
+    },
+    Pypi {
+        package: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        repo: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct ClusterLibraryStatuses {
+    #[serde(default)]
+    pub library_statuses: Vec<LibraryFullStatus>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LibraryFullStatus {
+    /// Kept as JSON, the cluster can also have library types brichka does not install, e.g. CRAN packages
+    pub library: serde_json::Value,
+    pub status: String,
+    #[serde(default)]
+    pub messages: Vec<String>,
+    #[serde(default)]
+    pub is_library_for_all_clusters: bool,
+}
+
+pub async fn cluster_status(cluster_id: String) -> Result<ClusterLibraryStatuses> {
+    let path = format!("/api/2.0/libraries/cluster-status?cluster_id={}", cluster_id);
+    let response = crate::client::call_databricks_api::<ClusterLibraryStatuses>(Method::GET, &path, None).await?;
+    Ok(response)
+}
+
+/// Install and uninstall requests respond with an empty object, or with an error for invalid libraries
+async fn update<T: Serialize>(path: &str, cluster_id: String, libraries: &[T]) -> Result<()> {
+    let request_body = serde_json::json!({
+        "cluster_id": cluster_id,
+        "libraries": libraries,
+    });
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, path, Some(request_body.to_string())).await?;
+    crate::client::check_api_error(&response)
+}
+
+pub async fn install<T: Serialize>(cluster_id: String, libraries: &[T]) -> Result<()> {
+    update("/api/2.0/libraries/install", cluster_id, libraries).await
+}
+
+/// Libraries are removed when the cluster restarts
+pub async fn uninstall(cluster_id: String, libraries: &[Library]) -> Result<()> {
+    update("/api/2.0/libraries/uninstall", cluster_id, libraries).await
+}
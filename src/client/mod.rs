@@ -1,7 +1,14 @@
 pub mod cluster;
 pub mod context;
+pub mod files;
 pub mod command;
 pub mod uc;
+pub mod warehouse;
+pub mod statement;
+pub mod library;
+pub mod permissions;
+pub mod scim;
+pub mod spark;
 
 
 use anyhow::{Context, Result};
@@ -69,17 +76,44 @@ async fn get_auth_config() -> Result<DatabricksAuthConfig> {
             { 
                 let token = get_token_cli(&path).await?;
                 let host = get_host_cli(&path, &profile).await?;
-                Ok(DatabricksAuthConfig{token: token, host: host})
+                Ok(DatabricksAuthConfig{token, host})
             },
-        AuthConfig::Token { value, host } => Ok(DatabricksAuthConfig{token: value, host: host}),
+        AuthConfig::Token { value, host } => Ok(DatabricksAuthConfig{token: value, host}),
     }
 }
 
-async fn call_databricks_api<T>(method: reqwest::Method, path: &str, body: Option<String>) -> Result<T>
-where T: for<'de> Deserialize<'de>
-{
-    let debug = CONTEXT.get().unwrap().debug;
+/// Fails with the error of a Databricks API response, for endpoints whose successful response is not otherwise checked
+fn check_api_error(response: &serde_json::Value) -> Result<()> {
+    if let Some(error_code) = response.get("error_code").and_then(|code| code.as_str()) {
+        let message = response.get("message").and_then(|message| message.as_str()).unwrap_or_default();
+        anyhow::bail!("{}: {}", error_code, message);
+    }
+    // The 1.2 API reports errors as a single message
+    if let Some(error) = response.get("error").and_then(|error| error.as_str()) {
+        anyhow::bail!("{}", error);
+    }
+    Ok(())
+}
+
+/// Appends query parameters to an API path, percent-encoding their values
+fn with_query(path: &str, params: &[(&str, String)]) -> String {
+    let mut url = reqwest::Url::parse("https://localhost").expect("Base URL is valid");
+    url.query_pairs_mut().extend_pairs(params);
+    format!("{}?{}", path, url.query().unwrap_or_default())
+}
+
+/// Returns the URL of a page in the workspace web UI
+pub async fn workspace_url(path: &str) -> Result<String> {
+    let config = AuthConfig::read_global().await.context("Failed to read authentication config")?;
+    let host = match config {
+        AuthConfig::DatabricksCli { path, profile } => get_host_cli(&path, &profile).await?,
+        AuthConfig::Token { host, .. } => host,
+    };
+    Ok(format!("{}{}", host.trim_end_matches('/'), path))
+}
 
+/// Builds an authenticated request to the Databricks API, for endpoints that don't respond with JSON
+async fn databricks_request(method: reqwest::Method, path: &str) -> Result<reqwest::RequestBuilder> {
     let auth_config = get_auth_config().await?;
     let client = reqwest::Client::builder().build()?;
 
@@ -87,7 +121,15 @@ where T: for<'de> Deserialize<'de>
 
     let url = format!("{}{}",auth_config.host, path);
 
-    let base_request = client.request(method, url).header("Authorization", token_header);
+    Ok(client.request(method, url).header("Authorization", token_header))
+}
+
+async fn call_databricks_api<T>(method: reqwest::Method, path: &str, body: Option<String>) -> Result<T>
+where T: for<'de> Deserialize<'de>
+{
+    let debug = CONTEXT.get().unwrap().debug;
+
+    let base_request = databricks_request(method, path).await?;
 
     let request = if let Some(body_str) = body {
         base_request.body(body_str)
@@ -108,3 +150,14 @@ where T: for<'de> Deserialize<'de>
 
     serde_json::from_str::<T>(&response_text).context("Failed to parse Databricks API output")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_query_values() {
+        let params = [("page_size", "100".to_string()), ("page_token", "a+b/c==".to_string())];
+        assert_eq!(with_query("/api/2.1/clusters/list", &params), "/api/2.1/clusters/list?page_size=100&page_token=a%2Bb%2Fc%3D%3D");
+    }
+}
@@ -0,0 +1,14 @@
+use reqwest::Method;
+use anyhow::Result;
+
+
+/// Whether the user can read the permissions of the cluster, which takes `CAN_MANAGE` or a workspace admin
+pub async fn can_manage_cluster(cluster_id: String) -> Result<bool> {
+    let path = format!("/api/2.0/permissions/clusters/{}", cluster_id);
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::GET, &path, None).await?;
+    if response.get("error_code").and_then(|code| code.as_str()) == Some("PERMISSION_DENIED") {
+        return Ok(false);
+    }
+    crate::client::check_api_error(&response)?;
+    Ok(true)
+}
@@ -0,0 +1,24 @@
+use reqwest::Method;
+use serde::Deserialize;
+use anyhow::Result;
+
+
+#[derive(Debug, Deserialize)]
+pub struct CurrentUser {
+    #[serde(rename = "userName")]
+    pub user_name: String,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Group {
+    pub display: String,
+}
+
+/// The user brichka authenticates as
+pub async fn me() -> Result<CurrentUser> {
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::GET, "/api/2.0/preview/scim/v2/Me", None).await?;
+    crate::client::check_api_error(&response)?;
+    Ok(serde_json::from_value(response)?)
+}
@@ -0,0 +1,45 @@
+use reqwest::Method;
+use serde::Deserialize;
+use anyhow::Result;
+
+/// Port the Spark UI listens on in the driver, reachable through the driver proxy
+const SPARK_UI_PORT: u16 = 40001;
+
+fn spark_api_path(cluster_id: &str, path: &str) -> String {
+    format!("/driver-proxy-api/o/0/{}/{}/api/v1{}", cluster_id, SPARK_UI_PORT, path)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Application {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub job_id: u64,
+    pub name: String,
+    pub status: String,
+    pub job_group: Option<String>,
+    pub num_tasks: u64,
+    pub num_completed_tasks: u64,
+    #[serde(default)]
+    pub num_skipped_tasks: u64,
+    pub num_active_stages: u64,
+}
+
+/// Lists the Spark applications of the cluster, Databricks runs a single one per cluster
+pub async fn list_applications(cluster_id: &str) -> Result<Vec<Application>> {
+    let path = spark_api_path(cluster_id, "/applications");
+    crate::client::call_databricks_api::<Vec<Application>>(Method::GET, &path, None).await
+}
+
+pub async fn get_job(cluster_id: &str, application_id: &str, job_id: u64) -> Result<Job> {
+    let path = spark_api_path(cluster_id, &format!("/applications/{}/jobs/{}", application_id, job_id));
+    crate::client::call_databricks_api::<Job>(Method::GET, &path, None).await
+}
+
+pub async fn list_running_jobs(cluster_id: &str, application_id: &str) -> Result<Vec<Job>> {
+    let path = spark_api_path(cluster_id, &format!("/applications/{}/jobs?status=running", application_id));
+    crate::client::call_databricks_api::<Vec<Job>>(Method::GET, &path, None).await
+}
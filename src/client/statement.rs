@@ -0,0 +1,105 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Disposition {
+    /// Results are returned in the API response, limited to 25 MiB
+    Inline,
+    /// Results are returned as presigned URLs to chunks in cloud storage
+    ExternalLinks,
+}
+
+
+#[derive(Debug, Deserialize)]
+pub struct StatementResponse {
+    pub statement_id: String,
+    pub status: StatementStatus,
+    pub manifest: Option<ResultManifest>,
+    pub result: Option<ResultData>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatementStatus {
+    pub state: String,
+    pub error: Option<StatementError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatementError {
+    pub error_code: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResultManifest {
+    pub schema: ResultSchema,
+    pub total_chunk_count: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResultSchema {
+    #[serde(default)]
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Column {
+    pub name: String,
+    pub type_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResultData {
+    pub data_array: Option<Vec<Vec<Option<String>>>>,
+    pub next_chunk_index: Option<usize>,
+    pub external_links: Option<Vec<ExternalLink>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExternalLink {
+    pub external_link: String,
+}
+
+pub async fn execute(statement: String, warehouse_id: String, disposition: Disposition) -> Result<StatementResponse> {
+    let request_body = serde_json::json!({
+        "statement": statement,
+        "warehouse_id": warehouse_id,
+        "wait_timeout": "10s",
+        "on_wait_timeout": "CONTINUE",
+        "disposition": disposition,
+        "format": "JSON_ARRAY"
+    });
+
+    let response = crate::client::call_databricks_api::<StatementResponse>(Method::POST, "/api/2.0/sql/statements", Some(request_body.to_string())).await?;
+    Ok(response)
+}
+
+pub async fn get(statement_id: String) -> Result<StatementResponse> {
+    let path = format!("/api/2.0/sql/statements/{}", statement_id);
+    let response = crate::client::call_databricks_api::<StatementResponse>(Method::GET, &path, None).await?;
+    Ok(response)
+}
+
+pub async fn cancel(statement_id: String) -> Result<()> {
+    let path = format!("/api/2.0/sql/statements/{}/cancel", statement_id);
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, &path, None).await?;
+    crate::client::check_api_error(&response)
+}
+
+pub async fn get_result_chunk(statement_id: String, chunk_index: usize) -> Result<ResultData> {
+    let path = format!("/api/2.0/sql/statements/{}/result/chunks/{}", statement_id, chunk_index);
+    let response = crate::client::call_databricks_api::<ResultData>(Method::GET, &path, None).await?;
+    Ok(response)
+}
+
+/// Downloads a result chunk from a presigned URL. The URL must not receive the Databricks token
+pub async fn download_external_link(url: &str) -> Result<Vec<u8>> {
+    let response = reqwest::get(url).await?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("Failed to download result chunk: {}", status);
+    }
+    Ok(response.bytes().await?.to_vec())
+}
@@ -0,0 +1,46 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+
+
+#[derive(Deserialize)]
+pub struct ListWarehousesResponse {
+    #[serde(default)]
+    pub warehouses: Vec<Warehouse>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Warehouse {
+    pub id: String,
+    pub name: String,
+    pub state: String,
+}
+
+pub async fn list() -> Result<ListWarehousesResponse> {
+    let response = crate::client::call_databricks_api::<ListWarehousesResponse>(Method::GET, "/api/2.0/sql/warehouses", None).await?;
+    Ok(response)
+}
+
+
+#[derive(Deserialize)]
+pub struct GetWarehouseInfoResponse {
+    pub state: String
+}
+
+pub async fn get_info(warehouse_id: String) -> Result<GetWarehouseInfoResponse> {
+    let path = format!("/api/2.0/sql/warehouses/{}", warehouse_id);
+    let response = crate::client::call_databricks_api::<GetWarehouseInfoResponse>(Method::GET, &path, None).await?;
+    Ok(response)
+}
+
+pub async fn start(warehouse_id: String) -> Result<()> {
+    let path = format!("/api/2.0/sql/warehouses/{}/start", warehouse_id);
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, &path, None).await?;
+    crate::client::check_api_error(&response)
+}
+
+pub async fn stop(warehouse_id: String) -> Result<()> {
+    let path = format!("/api/2.0/sql/warehouses/{}/stop", warehouse_id);
+    let response = crate::client::call_databricks_api::<serde_json::Value>(Method::POST, &path, None).await?;
+    crate::client::check_api_error(&response)
+}
@@ -1,45 +1,420 @@
-use anyhow::Result;
-use tokio::time::{ sleep, Duration };
+use std::path::PathBuf;
 
-use crate::config::ClusterConfig;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::{fs, process::Command, time::{ sleep, Duration, Instant }};
 
+use crate::{cli::ClusterFilter, client::cluster::{Cluster, ClusterEvent, GetClusterInfoResponse, TerminationReason}, config::ClusterConfig, progress::ProgressEvent};
 
-pub async fn list() -> Result<()> {
-    let response = crate::client::cluster::list().await?;
-    let result_json = serde_json::to_string(&response.clusters)?;
+#[derive(Serialize)]
+struct ClusterDetails {
+    #[serde(flatten)]
+    info: GetClusterInfoResponse,
+    spark_ui_url: String,
+}
+
+
+fn matches_filter(cluster: &Cluster, filter: &ClusterFilter) -> bool {
+    let name_matches = filter.name.as_ref().is_none_or(|name| name.is_match(&cluster.name));
+    let owner_matches = filter.owner.as_ref().is_none_or(|owner| {
+        cluster.creator_user_name.as_ref().is_some_and(|creator| creator.eq_ignore_ascii_case(owner))
+    });
+    let tags_match = filter.tags.iter().all(|(key, value)| match (cluster.custom_tags.get(key), value) {
+        (Some(actual), Some(expected)) => actual == expected,
+        (Some(_), None) => true,
+        (None, _) => false,
+    });
+    name_matches && owner_matches && tags_match
+}
+
+/// Lists the clusters matching the filter, running clusters first and then by name
+pub async fn list_filtered(filter: &ClusterFilter) -> Result<Vec<Cluster>> {
+    let mut clusters = crate::client::cluster::list(&filter.sources, &filter.states).await?
+        .into_iter()
+        .filter(|cluster| matches_filter(cluster, filter))
+        .collect::<Vec<Cluster>>();
+    clusters.sort_by_key(|cluster| (cluster.state != "RUNNING", cluster.name.to_lowercase()));
+    Ok(clusters)
+}
+
+pub async fn list(filter: ClusterFilter) -> Result<()> {
+    let clusters = list_filtered(&filter).await?;
+    let result_json = serde_json::to_string(&clusters)?;
     println!("{}", result_json);
     Ok(())
 }
 
 pub async fn status() -> Result<()> {
-    let cluster = ClusterConfig::read_local().await.or(ClusterConfig::read_global().await)?;
+    let cluster = ClusterConfig::read_current().await?;
     let state = crate::client::cluster::get_info(cluster.id).await?.state;
     println!("{}", state);
     Ok(())
 }
 
-pub async fn start() -> Result<()> {
-    let cluster = ClusterConfig::read_local().await.or(ClusterConfig::read_global().await)?;
-    let cluster_id = cluster.id;
+/// Formats the termination reason as `CODE (TYPE): message`
+pub fn describe_termination(reason: &TerminationReason) -> String {
+    let mut description = reason.code.to_owned().unwrap_or("UNKNOWN".to_string());
+    if let Some(tpe) = &reason.tpe {
+        description.push_str(&format!(" ({})", tpe));
+    }
+
+    let message = match reason.parameters.get("databricks_error_message") {
+        Some(message) => message.to_owned(),
+        None => reason.parameters.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<String>>().join(", "),
+    };
+    if !message.is_empty() {
+        description.push_str(&format!(": {}", message));
+    }
+    description
+}
 
-    let state = crate::client::cluster::get_info(cluster_id.to_owned()).await?.state;
-    match state.as_str() {
-        "RUNNING" => return Ok(()),
-        "TERMINATED" => {
-            crate::client::cluster::start(cluster_id.to_owned()).await?;
+fn format_workers(info: &GetClusterInfoResponse) -> String {
+    let node_type = info.node_type_id.as_deref().unwrap_or("unknown");
+    match (&info.autoscale, info.num_workers) {
+        (Some(autoscale), _) => format!("{}-{} × {} (autoscale)", autoscale.min_workers, autoscale.max_workers, node_type),
+        (None, Some(0)) => "none (single node)".to_string(),
+        (None, Some(workers)) => format!("{} × {}", workers, node_type),
+        (None, None) => "unknown".to_string(),
+    }
+}
+
+fn format_details(details: &ClusterDetails) -> String {
+    let info = &details.info;
+    let name = info.cluster_name.as_deref().unwrap_or_default();
+    let mut lines = vec![format!("{} {} ({})", crate::commands::render_state(&info.state), name, info.cluster_id)];
+    let mut field = |label: &str, value: Option<String>| {
+        if let Some(value) = value {
+            lines.push(format!("{:<18}{}", format!("{}:", label), value));
         }
-        _ => {}
+    };
+
+    field("State", Some(match &info.state_message {
+        Some(message) if !message.is_empty() => format!("{} ({})", info.state, message),
+        _ => info.state.to_owned(),
+    }));
+    field("Runtime", info.spark_version.to_owned());
+    field("Driver", info.driver_node_type_id.to_owned().or(info.node_type_id.to_owned()));
+    field("Workers", Some(format_workers(info)));
+    field("Access mode", info.data_security_mode.to_owned());
+    field("Single user", info.single_user_name.to_owned());
+    field("Creator", info.creator_user_name.to_owned());
+    field("Auto-terminate", info.autotermination_minutes.map(|minutes| match minutes {
+        0 => "disabled".to_string(),
+        minutes => format!("after {} minutes of inactivity", minutes),
+    }));
+    if !info.custom_tags.is_empty() {
+        let tags = info.custom_tags.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<String>>();
+        field("Tags", Some(tags.join(", ")));
+    }
+    field("Last termination", info.termination_reason.as_ref().map(describe_termination));
+    field("Spark UI", Some(details.spark_ui_url.to_owned()));
+
+    lines.join("\n")
+}
+
+/// Returns the URL of the Spark UI of the cluster in the workspace web UI
+pub async fn spark_ui_url(cluster_id: &str) -> Result<String> {
+    crate::client::workspace_url(&format!("/#setting/clusters/{}/sparkUi", cluster_id)).await
+}
+
+/// Prints the configuration of the selected cluster as JSON, or as a human readable summary with `pretty`
+pub async fn info(pretty: bool) -> Result<()> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+    let info = crate::client::cluster::get_info(cluster_id.to_owned()).await?;
+    let spark_ui_url = spark_ui_url(&cluster_id).await?;
+    let details = ClusterDetails { info, spark_ui_url };
+
+    if pretty {
+        println!("{}", format_details(&details));
+    } else {
+        let result_json = serde_json::to_string(&details)?;
+        println!("{}", result_json);
+    }
+    Ok(())
+}
+
+/// Short description of a cluster event from the fields Databricks sets for the common event types
+fn describe_event(event: &ClusterEvent) -> String {
+    let details = &event.details;
+    let field = |key: &str| details.get(key).and_then(|value| value.as_str().map(str::to_string).or(value.as_u64().map(|n| n.to_string())));
+
+    let mut description = event.tpe.replace('_', " ").to_lowercase();
+    if let (Some(current), Some(target)) = (field("current_num_workers"), field("target_num_workers")) {
+        description.push_str(&format!(", workers {} → {}", current, target));
+    }
+    if let Some(code) = details.get("reason").and_then(|reason| reason.get("code")).and_then(|code| code.as_str()) {
+        description.push_str(&format!(", {}", code));
+    }
+    if let Some(cause) = field("cause") {
+        description.push_str(&format!(", {}", cause));
+    }
+    description
+}
+
+/// Reports the cluster events newer than `last_event_time` and advances it. Events are best effort, errors are ignored
+async fn report_new_events(cluster_id: &str, last_event_time: &mut u64, started: Instant) {
+    let request = serde_json::json!({
+        "cluster_id": cluster_id,
+        "start_time": *last_event_time + 1,
+        "order": "ASC",
+        "limit": EVENTS_PAGE_SIZE,
+    });
+    let Ok(response) = crate::client::cluster::get_events(request).await else {
+        return;
+    };
+    for event in response.events {
+        if event.timestamp <= *last_event_time {
+            continue;
+        }
+        crate::progress::report(ProgressEvent::ClusterEvent {
+            id: cluster_id,
+            tpe: &event.tpe,
+            message: describe_event(&event),
+            elapsed_secs: started.elapsed().as_secs(),
+        });
+        *last_event_time = (*last_event_time).max(event.timestamp);
     }
+}
+
+/// Polls after a start or restart request during which the cluster may still be reported in its previous state
+const STALE_STATE_GRACE_POLLS: u32 = 3;
 
+/// Whether `state` may still be the state from before the request, i.e. the cluster hasn't left it and the grace polls aren't over
+fn is_stale(state: &str, stale_state: Option<&str>, left_stale_state: bool, polls: u32) -> bool {
+    stale_state == Some(state) && !left_stale_state && polls <= STALE_STATE_GRACE_POLLS
+}
+
+/// Polls the cluster until it reaches `target_state`, reporting state transitions and cluster events as progress.
+/// `stale_state` is the state the cluster was in before the request, it is not taken as a result while it may be stale
+async fn await_state(cluster_id: String, target_state: &str, stale_state: Option<&str>, timeout: Option<Duration>) -> Result<()> {
+    let started = Instant::now();
+    let mut last_event_time = crate::config::unix_timestamp() * 1000;
+    let mut last_state = String::new();
+    let mut left_stale_state = false;
+    let mut polls = 0;
     loop {
+        polls += 1;
         let state = crate::client::cluster::get_info(cluster_id.to_owned()).await?.state;
-        if state == "RUNNING" {
+        let stale = is_stale(&state, stale_state, left_stale_state, polls);
+        left_stale_state |= stale_state != Some(state.as_str());
+        if state != last_state {
+            crate::progress::report(ProgressEvent::State {
+                resource: "cluster",
+                id: &cluster_id,
+                state: &state,
+                elapsed_secs: started.elapsed().as_secs(),
+            });
+            last_state = state.to_owned();
+        }
+        report_new_events(&cluster_id, &mut last_event_time, started).await;
+
+        if state == target_state && !stale {
             break;
-        } else if state == "ERROR" {
-           anyhow::bail!("Failed to start the cluster");
+        } else if state == "ERROR" || (state == "TERMINATED" && target_state != "TERMINATED" && !stale) {
+            anyhow::bail!("Cluster is `{}`", state);
+        } else if let Some(timeout) = timeout
+            && started.elapsed() >= timeout
+        {
+            anyhow::bail!("Cluster is still `{}` after {} seconds, stopped waiting for `{}`", state, timeout.as_secs(), target_state);
         }
         sleep(Duration::from_secs(2)).await;
     };
+    Ok(())
+}
+
+/// `timeout` is in seconds, without it the cluster is awaited until it is running
+pub async fn start(timeout: Option<u64>) -> Result<()> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+
+    let state = crate::client::cluster::get_info(cluster_id.to_owned()).await?.state;
+    match state.as_str() {
+        "RUNNING" => return Ok(()),
+        "TERMINATED" => {
+            crate::client::cluster::start(cluster_id.to_owned()).await
+                .map_err(|e| crate::commands::permissions::explain_denied(e, &cluster_id, "start", "CAN_RESTART"))?;
+        }
+        _ => {}
+    }
+
+    await_state(cluster_id, "RUNNING", Some("TERMINATED"), timeout.map(Duration::from_secs)).await
+}
+
+pub async fn stop(wait: bool) -> Result<()> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+
+    let state = crate::client::cluster::get_info(cluster_id.to_owned()).await?.state;
+    if state == "TERMINATED" {
+        return Ok(());
+    }
+    crate::client::cluster::stop(cluster_id.to_owned()).await?;
+
+    if wait {
+        await_state(cluster_id, "TERMINATED", None, None).await?;
+    }
+
+    Ok(())
+}
+
+/// Restarting drops every execution context on the cluster
+pub async fn restart(wait: bool) -> Result<()> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+
+    let state = crate::client::cluster::get_info(cluster_id.to_owned()).await?.state;
+    if state == "TERMINATED" {
+        anyhow::bail!("Cluster is terminated. Start it with `brichka cluster start`");
+    }
+    crate::client::cluster::restart(cluster_id.to_owned()).await
+        .map_err(|e| crate::commands::permissions::explain_denied(e, &cluster_id, "restart", "CAN_RESTART"))?;
+
+    if wait {
+        await_state(cluster_id, "RUNNING", Some("RUNNING"), None).await?;
+    }
 
     Ok(())
 }
+
+/// Largest page the events API returns
+const EVENTS_PAGE_SIZE: usize = 500;
+
+/// Timestamp in milliseconds `secs_ago` seconds before `now_ms`, the epoch for durations longer than that
+fn ms_before(now_ms: u64, secs_ago: u64) -> u64 {
+    now_ms.saturating_sub(secs_ago.saturating_mul(1000))
+}
+
+/// Prints the events of the selected cluster as JSON, most recent first.
+/// `since` and `until` are in seconds before now
+pub async fn events(since: Option<u64>, until: Option<u64>, event_types: Vec<String>, limit: usize) -> Result<()> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+    let now_ms = crate::config::unix_timestamp() * 1000;
+
+    let mut request = serde_json::json!({
+        "cluster_id": cluster_id,
+        "order": "DESC",
+        "limit": limit.min(EVENTS_PAGE_SIZE),
+    });
+    if let Some(since) = since {
+        request["start_time"] = ms_before(now_ms, since).into();
+    }
+    if let Some(until) = until {
+        request["end_time"] = ms_before(now_ms, until).into();
+    }
+    if !event_types.is_empty() {
+        request["event_types"] = event_types.into();
+    }
+
+    let mut events: Vec<ClusterEvent> = Vec::new();
+    let mut next_request = Some(request);
+    while let Some(request) = next_request
+        && events.len() < limit
+    {
+        let response = crate::client::cluster::get_events(request).await?;
+        events.extend(response.events);
+        next_request = response.next_page;
+    }
+    events.truncate(limit);
+
+    let result_json = serde_json::to_string(&events)?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+async fn current_branch() -> Result<String> {
+    let cwd = crate::CONTEXT.get().unwrap().cwd.to_owned();
+    let output = Command::new("git").args(["-C", &cwd, "branch", "--show-current"]).output().await
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Failed to get the current git branch: {}", stderr.trim());
+    }
+
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if branch.is_empty() {
+        anyhow::bail!("The cluster template uses `{{branch}}`, but no git branch is checked out");
+    }
+    Ok(branch)
+}
+
+/// Replaces `{branch}` in every string of the template
+fn fill_placeholders(value: &mut serde_json::Value, branch: &str) {
+    match value {
+        serde_json::Value::String(string) => *string = string.replace("{branch}", branch),
+        serde_json::Value::Array(values) => values.iter_mut().for_each(|value| fill_placeholders(value, branch)),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(|value| fill_placeholders(value, branch)),
+        _ => {},
+    }
+}
+
+/// Creates a cluster from a `/clusters/create` request in the template, waits until it is running and selects it for the working directory.
+/// The template can list `libraries` to install and use `{branch}` for the current git branch
+pub async fn create(from: String, name: Option<String>, timeout: Option<u64>) -> Result<()> {
+    let path = PathBuf::from(&crate::CONTEXT.get().unwrap().cwd).join(from);
+    let raw_json = fs::read_to_string(&path).await.with_context(|| format!("Failed to read the cluster template `{}`", path.display()))?;
+    let mut spec = serde_json::from_str::<serde_json::Value>(&raw_json).context("Failed to parse the cluster template")?;
+    let fields = spec.as_object_mut().context("The cluster template must be a JSON object")?;
+
+    let libraries = fields.remove("libraries");
+    if let Some(name) = name {
+        fields.insert("cluster_name".to_string(), name.into());
+    }
+    if !fields.contains_key("cluster_name") {
+        anyhow::bail!("The cluster template has no `cluster_name`, set it or pass --name");
+    }
+    if spec.to_string().contains("{branch}") {
+        fill_placeholders(&mut spec, &current_branch().await?);
+    }
+
+    let cluster_id = crate::client::cluster::create(&spec).await?;
+    let cluster_name = spec["cluster_name"].as_str().unwrap_or_default().to_string();
+    let config = ClusterConfig { id: cluster_id.to_owned(), name: cluster_name, ephemeral: true };
+    config.write_local().await?;
+
+    if let Some(serde_json::Value::Array(libraries)) = libraries
+        && !libraries.is_empty()
+    {
+        crate::client::library::install(cluster_id.to_owned(), &libraries).await?;
+    }
+
+    await_state(cluster_id, "RUNNING", None, timeout.map(Duration::from_secs)).await?;
+
+    let result_json = serde_json::to_string(&config)?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+/// Permanently deletes the selected cluster. Only clusters created with `brichka cluster create` are deleted without `force`
+pub async fn destroy(force: bool) -> Result<()> {
+    let cluster = ClusterConfig::read_current().await?;
+    if !cluster.ephemeral && !force {
+        anyhow::bail!("Cluster `{}` was not created with `brichka cluster create`. Use --force to delete it permanently anyway", cluster.name);
+    }
+
+    crate::client::cluster::permanent_delete(cluster.id.to_owned()).await?;
+
+    if let Ok(local) = ClusterConfig::read_local().await
+        && local.id == cluster.id
+    {
+        ClusterConfig::remove_local().await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn treats_previous_state_as_stale_until_left_or_grace_polls_are_over() {
+        assert!(is_stale("RUNNING", Some("RUNNING"), false, 1));
+        assert!(!is_stale("RUNNING", Some("RUNNING"), true, 2));
+        assert!(!is_stale("RUNNING", Some("RUNNING"), false, STALE_STATE_GRACE_POLLS + 1));
+        assert!(is_stale("TERMINATED", Some("TERMINATED"), false, STALE_STATE_GRACE_POLLS));
+        assert!(!is_stale("RUNNING", None, false, 1));
+    }
+
+    #[test]
+    fn computes_event_times_without_overflow() {
+        assert_eq!(ms_before(10_000_000, 60), 10_000_000 - 60_000);
+        assert_eq!(ms_before(10_000_000, 200_000_000_000_000 * 24 * 60 * 60), 0);
+        assert_eq!(ms_before(10_000_000, u64::MAX), 0);
+    }
+}
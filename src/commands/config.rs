@@ -3,47 +3,103 @@ use std::io::Cursor;
 use anyhow::{ Result, Context };
 use skim::prelude::*;
 
-use crate::config::ClusterConfig;
-
-fn render_cluster_state(state: &str) -> &str {
-    match state {
-        "RUNNING" =>  "🟢",
-        "PENDING" => "🔵",
-        "RESTARTING" => "♻️",
-        "RESIZING" => "⚙️",
-        "TERMINATING" => "🔻",
-        "TERMINATED" => "🔴",
-        "ERROR" => "⚠️",
-        "UNKNOWN" => "❔",
-        _ => "❔"
+use crate::{cli::{ClusterFilter, NamePattern}, client::cluster::Cluster, commands::permissions::ClusterAccess, config::{ClusterConfig, WarehouseConfig}};
+
+/// The cluster `--name` selects without opening the picker: the only one whose name matches `--name` exactly
+/// (ignoring case), otherwise the only one matching the filters. `None` if the picker has to choose
+fn find_cluster_by_name<'a>(clusters: &'a [Cluster], pattern: &NamePattern) -> Option<&'a Cluster> {
+    let mut exact_matches = clusters.iter().filter(|c| pattern.is_exact_match(&c.name));
+    match (exact_matches.next(), exact_matches.next()) {
+        (Some(cluster), None) => Some(cluster),
+        (Some(_), Some(_)) => None,
+        (None, _) if clusters.len() == 1 => clusters.first(),
+        (None, _) => None,
     }
 }
 
-pub async fn select_cluster(global: bool) -> Result<()> {
-    let clusters = crate::client::cluster::list().await?.clusters;
+/// Marks clusters the user can't attach to and those the user manages
+fn render_access(access: Option<ClusterAccess>) -> &'static str {
+    match access {
+        Some(ClusterAccess::None) => " 🔒 no access",
+        Some(ClusterAccess::Manage) => " (manage)",
+        None => "",
+    }
+}
+
+pub async fn select_cluster(id: Option<String>, filter: ClusterFilter, attachable_only: bool, global: bool) -> Result<()> {
+    let config = if let Some(id) = id {
+        let info = crate::client::cluster::get_info(id.to_owned()).await
+            .with_context(|| format!("Cluster `{}` not found", id))?;
+        ClusterConfig { id, name: info.cluster_name.unwrap_or_default(), ephemeral: false }
+    } else {
+        let clusters = crate::commands::cluster::list_filtered(&filter).await?;
+        let named_cluster = filter.name.as_ref().and_then(|pattern| find_cluster_by_name(&clusters, pattern));
+        if let Some(cluster) = named_cluster {
+            return write_cluster_config(ClusterConfig::new(cluster), global).await;
+        }
+
+        // Without the current user every cluster is shown unmarked
+        let accesses = match crate::client::scim::me().await {
+            Ok(user) => crate::commands::permissions::cluster_accesses(&user, &clusters).await,
+            Err(_) => vec![None; clusters.len()],
+        };
+        let (clusters, accesses): (Vec<Cluster>, Vec<Option<ClusterAccess>>) = clusters
+            .into_iter()
+            .zip(accesses)
+            .filter(|(_, access)| !attachable_only || *access != Some(ClusterAccess::None))
+            .unzip();
+        if clusters.is_empty() {
+            anyhow::bail!("No clusters match the filters");
+        }
+
+        let items: String = clusters
+            .iter()
+            .zip(accesses)
+            .map(|(c, access)| format!("{} {} ({}){}", crate::commands::render_state(&c.state), c.name, c.id, render_access(access)))
+            .collect::<Vec<String>>().join("\n");
+
+        let selected_index = run_skim(&items, "Select cluster: ")?;
+        ClusterConfig::new(clusters.get(selected_index).unwrap())
+    };
+
+    write_cluster_config(config, global).await
+}
+
+async fn write_cluster_config(config: ClusterConfig, global: bool) -> Result<()> {
+    if global {
+        config.write_global().await?
+    } else {
+        config.write_local().await?
+    }
     
-    let items: String = clusters
+    Ok(())
+}
+
+pub async fn select_warehouse(global: bool) -> Result<()> {
+    let warehouses = crate::client::warehouse::list().await?.warehouses;
+
+    let items: String = warehouses
         .iter()
-        .map(|c| format!("{} {} ({})", render_cluster_state(&c.state), c.name, c.id))
+        .map(|w| format!("{} {} ({})", crate::commands::render_state(&w.state), w.name, w.id))
         .collect::<Vec<String>>().join("\n");
 
-    let selected_index = run_skim(&items)?;
-    let selected_cluster = clusters.get(selected_index).unwrap();
-    let config = ClusterConfig::new(selected_cluster);
+    let selected_index = run_skim(&items, "Select warehouse: ")?;
+    let selected_warehouse = warehouses.get(selected_index).unwrap();
+    let config = WarehouseConfig::new(selected_warehouse);
     if global {
         config.write_global().await?
     } else {
         config.write_local().await?
     }
-    
+
     Ok(())
 }
 
-fn run_skim(items: &str) -> Result<usize> {
+pub fn run_skim(items: &str, prompt: &str) -> Result<usize> {
     let options = SkimOptionsBuilder::default()
         .height("50%".to_string())
         .multi(false)
-        .prompt("Select cluster: ".to_string())
+        .prompt(prompt.to_string())
         .build()
         .unwrap();
 
@@ -67,7 +123,7 @@ fn run_skim(items: &str) -> Result<usize> {
 
 pub async fn configure_token_auth(token: String, host: String) -> Result<()> {
 
-    let config = crate::config::AuthConfig::Token { value: token, host: host };
+    let config = crate::config::AuthConfig::Token { value: token, host };
 
     config.write_global().await
 }
@@ -75,7 +131,36 @@ pub async fn configure_token_auth(token: String, host: String) -> Result<()> {
 
 pub async fn configure_cli_auth(path: String, profile: String) -> Result<()> {
 
-    let config = crate::config::AuthConfig::DatabricksCli { path: path, profile: profile };
+    let config = crate::config::AuthConfig::DatabricksCli { path, profile };
 
     config.write_global().await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clusters(names: &[&str]) -> Vec<Cluster> {
+        names.iter().enumerate()
+            .map(|(index, name)| serde_json::from_value(serde_json::json!({
+                "cluster_id": format!("0101-{}", index),
+                "cluster_name": name,
+                "state": "RUNNING",
+            })).unwrap())
+            .collect()
+    }
+
+    fn selected(names: &[&str], pattern: &str) -> Option<String> {
+        let clusters = clusters(names);
+        find_cluster_by_name(&clusters, &NamePattern::new(pattern)).map(|c| c.name.to_owned())
+    }
+
+    #[test]
+    fn selects_exact_or_only_match_and_leaves_the_rest_to_the_picker() {
+        assert_eq!(selected(&["etl-dev", "etl-dev-v2"], "ETL-dev"), Some("etl-dev".to_string()));
+        assert_eq!(selected(&["etl-prod"], "^etl-"), Some("etl-prod".to_string()));
+        assert_eq!(selected(&["etl-dev", "etl-prod"], "^etl-"), None);
+        assert_eq!(selected(&["etl", "ETL"], "etl"), None);
+        assert_eq!(selected(&[], "etl"), None);
+    }
+}
@@ -0,0 +1,298 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{commands::run::CellOutput, config::{ClusterConfig, ContextConfig, ContextHistory, CreatedContext, CreatedContextsConfig}, language::Language, progress::ProgressEvent};
+
+/// Lists temporary views together with the views of the current schema
+const SQL_INSPECT: &str = "SHOW VIEWS";
+
+/// The probes print a JSON array of `{"name", "type", "dataframe"}` objects and don't define anything in the context
+const PYTHON_INSPECT: &str = r#"print(__import__("json").dumps([
+    {"name": n, "type": type(v).__module__ + "." + type(v).__name__, "dataframe": type(v).__name__ == "DataFrame" and type(v).__module__.startswith("pyspark")}
+    for n, v in list(globals().items())
+    if not n.startswith("_")
+    and type(v).__name__ not in ("module", "type", "builtin_function_or_method")
+    and n not in ("spark", "sc", "sqlContext", "sql", "table", "udf", "dbutils", "display", "displayHTML", "getArgument", "In", "Out", "exit", "quit", "get_ipython")
+]))"#;
+
+const SCALA_INSPECT: &str = r#"println($intp.definedTerms.map(_.toString).filterNot(_.contains("$")).distinct.map { name =>
+  val tpe = $intp.typeOfTerm(name).toString.stripPrefix("=> ")
+  val escaped = tpe.replace("\\", "\\\\").replace("\"", "\\\"")
+  s"""{"name":"$name","type":"$escaped","dataframe":${tpe.contains("DataFrame") || tpe.contains("Dataset[")}}"""
+}.mkString("[", ",", "]"))"#;
+
+const R_INSPECT: &str = r#"local({
+  names <- ls(envir = globalenv())
+  types <- vapply(names, function(n) class(get(n, envir = globalenv()))[1], "")
+  dataframes <- ifelse(types %in% c("SparkDataFrame", "tbl_spark"), "true", "false")
+  cat(paste0("[", paste0(sprintf('{"name":"%s","type":"%s","dataframe":%s}', names, types, dataframes), collapse = ","), "]"))
+})"#;
+
+
+#[derive(Serialize)]
+struct ContextSummary {
+    name: String,
+    id: String,
+    cluster_id: Option<String>,
+    status: String,
+}
+
+pub async fn list() -> Result<()> {
+    let selected_cluster_id = ClusterConfig::read_current().await.ok().map(|cluster| cluster.id);
+
+    let mut summaries = Vec::new();
+    for context in ContextConfig::list_local().await? {
+        let cluster_id = context.cluster_id.to_owned().or(selected_cluster_id.to_owned());
+        let status = match cluster_id.to_owned() {
+            Some(cluster_id) => crate::client::context::get_status(cluster_id, context.id.to_owned()).await
+                .map(|response| response.status)
+                .unwrap_or("Unavailable".to_string()),
+            None => "Unknown".to_string(),
+        };
+        summaries.push(ContextSummary { name: context.name, id: context.id, cluster_id, status });
+    }
+
+    let result_json = serde_json::to_string(&summaries)?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+pub async fn destroy(name: String) -> Result<()> {
+    let context = ContextConfig::read_local(&name).await?;
+    let cluster_id = match context.cluster_id.to_owned() {
+        Some(cluster_id) => cluster_id,
+        None => ClusterConfig::read_current().await?.id,
+    };
+
+    crate::commands::destroy_context(cluster_id, context.id.to_owned()).await?;
+    ContextHistory::clear_local(&name).await?;
+    context.remove_local().await
+}
+
+/// Creates a new execution context and executes the cells recorded for the previous one
+pub async fn replay_history(name: &str, language: Language) -> Result<ContextConfig> {
+    let history = ContextHistory::read_local(name).await?;
+    let sent_files = ContextConfig::read_local(name).await.map(|context| context.sent_files).unwrap_or_default();
+
+    crate::commands::init::init(name.to_string(), language).await?;
+    // `init` clears the history. Restore it before replaying, so a failing cell doesn't lose the cells after it
+    ContextHistory::write_local(name, &history).await?;
+    let mut context = ContextConfig::read_local(name).await?;
+    let cluster_id = context.cluster_id.to_owned().context("Execution context is missing its cluster")?;
+
+    let total = history.len();
+    for (index, cell) in history.into_iter().enumerate() {
+        crate::progress::report(ProgressEvent::Replay { cell: index + 1, total_cells: total });
+        crate::commands::run::execute_cell(cell.code, cell.language, cluster_id.to_owned(), context.id.to_owned()).await
+            .with_context(|| format!("Failed to replay cell {}/{}", index + 1, total))?;
+    }
+
+    // Files sent to the previous context are part of the replayed cells
+    context.sent_files = sent_files;
+    context.write_local().await?;
+
+    Ok(context)
+}
+
+pub async fn replay(name: String, start: bool) -> Result<()> {
+    let previous_context = ContextConfig::read_local(&name).await.ok();
+    let language = previous_context.and_then(|context| context.language).unwrap_or(Language::Sql);
+
+    let cluster_id = ClusterConfig::read_current().await?.id;
+    crate::commands::run::check_cluster_state(cluster_id, start).await?;
+
+    replay_history(&name, language).await?;
+    Ok(())
+}
+
+/// Contexts created more recently may still be used by a run that hasn't recorded its context yet
+const GC_MIN_AGE_SECS: u64 = 60 * 60;
+
+/// Whether the process is still running. Only known on Linux, elsewhere the age of the context decides
+fn is_running(pid: u32) -> bool {
+    cfg!(target_os = "linux") && std::path::Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+/// Whether a context may still be in use by the run that created it, e.g. the temporary context of a run in progress
+fn may_be_in_use(context: &CreatedContext, now: u64) -> bool {
+    now.saturating_sub(context.created_at) < GC_MIN_AGE_SECS
+        || context.pid.is_some_and(|pid| pid != std::process::id() && is_running(pid))
+}
+
+/// Destroys every context created by brichka that is not referenced by a named context anymore.
+/// Contexts that may still be used by a run in progress are kept
+pub async fn gc() -> Result<()> {
+    let referenced_ids = ContextConfig::list_local().await?
+        .into_iter()
+        .map(|context| context.id)
+        .collect::<Vec<String>>();
+
+    let now = crate::config::unix_timestamp();
+    let mut destroyed: Vec<CreatedContext> = Vec::new();
+    for context in CreatedContextsConfig::read_local().await?.contexts {
+        if referenced_ids.contains(&context.id) || may_be_in_use(&context, now) {
+            continue;
+        }
+
+        // Contexts that are already gone, e.g. because the cluster was restarted, are forgotten as well
+        match crate::commands::destroy_context(context.cluster_id.to_owned(), context.id.to_owned()).await {
+            Ok(()) => destroyed.push(context),
+            Err(e) => eprintln!("Warning: failed to destroy execution context `{}`: {}", context.id, e),
+        }
+    }
+
+    let result_json = serde_json::to_string(&destroyed)?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ProbeEntry {
+    name: String,
+    #[serde(rename = "type")]
+    tpe: String,
+    dataframe: bool,
+}
+
+#[derive(Serialize)]
+struct TempView {
+    name: String,
+    namespace: String,
+}
+
+#[derive(Serialize)]
+struct Definition {
+    name: String,
+    #[serde(rename = "type")]
+    tpe: String,
+    language: Language,
+}
+
+#[derive(Serialize)]
+struct InspectError {
+    language: Language,
+    message: String,
+}
+
+#[derive(Serialize, Default)]
+struct ContextSnapshot {
+    temp_views: Vec<TempView>,
+    dataframes: Vec<Definition>,
+    variables: Vec<Definition>,
+    errors: Vec<InspectError>,
+}
+
+fn inspect_code(language: Language) -> &'static str {
+    match language {
+        Language::Sql => SQL_INSPECT,
+        Language::Python => PYTHON_INSPECT,
+        Language::Scala => SCALA_INSPECT,
+        Language::R => R_INSPECT,
+    }
+}
+
+fn collect_temp_views(snapshot: &mut ContextSnapshot, rows: Vec<serde_json::Value>) {
+    for row in rows {
+        if row.get("isTemporary").and_then(|v| v.as_bool()) != Some(true) {
+            continue;
+        }
+        let field = |key: &str| row.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        snapshot.temp_views.push(TempView { name: field("viewName"), namespace: field("namespace") });
+    }
+}
+
+fn collect_definitions(snapshot: &mut ContextSnapshot, language: Language, output: &str) -> Result<()> {
+    let entries = serde_json::from_str::<Vec<ProbeEntry>>(output.trim()).context("Failed to parse the context definitions")?;
+    for entry in entries {
+        let definition = Definition { name: entry.name, tpe: entry.tpe, language };
+        if entry.dataframe {
+            snapshot.dataframes.push(definition);
+        } else {
+            snapshot.variables.push(definition);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the temporary views, dataframes and variables defined in the execution context
+pub async fn inspect(name: String, languages: Vec<Language>) -> Result<()> {
+    let context = ContextConfig::read_local(&name).await?;
+    let cluster_id = match context.cluster_id.to_owned() {
+        Some(cluster_id) => cluster_id,
+        None => ClusterConfig::read_current().await?.id,
+    };
+    crate::commands::run::check_cluster_state(cluster_id.to_owned(), false).await?;
+
+    let languages = if languages.is_empty() {
+        let mut defaults = vec![Language::Sql];
+        if let Some(language) = context.language
+            && language != Language::Sql
+        {
+            defaults.push(language);
+        }
+        defaults
+    } else {
+        languages
+    };
+
+    let mut snapshot = ContextSnapshot::default();
+    for language in languages {
+        let output = crate::commands::run::execute_cell(inspect_code(language).to_string(), language, cluster_id.to_owned(), context.id.to_owned()).await;
+        let collected = match output {
+            Ok(CellOutput::Table(rows)) if language == Language::Sql => {
+                collect_temp_views(&mut snapshot, rows);
+                Ok(())
+            },
+            Ok(CellOutput::Text(text)) if language != Language::Sql => collect_definitions(&mut snapshot, language, &text),
+            Ok(_) => Err(anyhow::anyhow!("Unexpected result type")),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = collected {
+            snapshot.errors.push(InspectError { language, message: e.to_string() });
+        }
+    }
+
+    let result_json = serde_json::to_string(&snapshot)?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Port of the escaping and the JSON object of the Scala probe
+    fn scala_probe_entry(name: &str, tpe: &str) -> String {
+        let escaped = tpe.replace('\\', "\\\\").replace('"', "\\\"");
+        format!(r#"{{"name":"{}","type":"{}","dataframe":false}}"#, name, escaped)
+    }
+
+    #[test]
+    fn scala_probe_escaping_round_trips_types() {
+        let tpe = r#"String("a\b") with Quote["\""]"#;
+        let mut snapshot = ContextSnapshot::default();
+        collect_definitions(&mut snapshot, Language::Scala, &format!("[{}]", scala_probe_entry("s", tpe))).unwrap();
+        assert_eq!(snapshot.variables.iter().map(|d| d.tpe.as_str()).collect::<Vec<&str>>(), vec![tpe]);
+    }
+
+    #[test]
+    fn keeps_contexts_that_may_still_be_in_use() {
+        let now = 10 * GC_MIN_AGE_SECS;
+        let context = |created_at: u64, pid: Option<u32>| CreatedContext { cluster_id: "0101-abc".to_string(), id: "1".to_string(), created_at, pid };
+        assert!(may_be_in_use(&context(now - 60, None), now));
+        assert!(!may_be_in_use(&context(now - GC_MIN_AGE_SECS, None), now));
+        assert!(!may_be_in_use(&context(0, Some(std::process::id())), now));
+        if cfg!(target_os = "linux") {
+            assert!(may_be_in_use(&context(0, Some(1)), now));
+        }
+    }
+
+    #[test]
+    fn collects_probe_definitions() {
+        let mut snapshot = ContextSnapshot::default();
+        let output = r#"[{"name":"df","type":"org.apache.spark.sql.Dataset[org.apache.spark.sql.Row]","dataframe":true},{"name":"n","type":"Int","dataframe":false}]"#;
+        collect_definitions(&mut snapshot, Language::Scala, output).unwrap();
+        assert_eq!(snapshot.dataframes.iter().map(|d| d.name.as_str()).collect::<Vec<&str>>(), vec!["df"]);
+        assert_eq!(snapshot.variables.iter().map(|d| d.name.as_str()).collect::<Vec<&str>>(), vec!["n"]);
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{client::library::Library, config::ClusterConfig, language::Language};
+
+/// Provided by the Databricks runtime and only declared for Metals
+const SPARK_ORGANIZATION: &str = "org.apache.spark";
+
+#[derive(Serialize)]
+struct DepsSummary {
+    /// Maven coordinates of every declared dependency
+    declared: Vec<String>,
+    /// Declared dependencies that are not installed on the cluster
+    missing: Vec<String>,
+    /// Declared dependencies installed on the cluster in another version
+    conflicts: Vec<VersionConflict>,
+}
+
+#[derive(Serialize)]
+struct VersionConflict {
+    declared: String,
+    installed: String,
+}
+
+/// Every `.sc` and `.scala` file in the directory and its subdirectories, skipping hidden and build directories.
+/// Symlinked directories are not followed, so links pointing back up the tree can't loop
+fn find_scala_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read `{}`", dir.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() && path.is_dir() {
+            continue;
+        }
+        if file_type.is_dir() {
+            find_scala_files(&path, files)?;
+        } else if Language::from_extension(&path) == Some(Language::Scala) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Converts a scala-cli dependency (`org::name::version` or `org:name:version`) to Maven coordinates.
+/// Returns `None` for Spark itself
+fn to_maven_coordinates(dep: &str, scala_version: Option<&str>) -> Result<Option<String>> {
+    let invalid = || anyhow::anyhow!("Invalid dependency `{}`, expected `organization::name::version`", dep);
+
+    let (organization, rest) = dep.split_once(':').ok_or_else(invalid)?;
+    let (cross_version, rest) = match rest.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    if rest.starts_with(':') {
+        anyhow::bail!("Dependency `{}` uses the full Scala version, which can not be installed as a cluster library", dep);
+    }
+    let (name, version) = rest.split_once(':').ok_or_else(invalid)?;
+    let version = version.trim_start_matches(':');
+    if organization.is_empty() || name.is_empty() || version.is_empty() {
+        return Err(invalid());
+    }
+
+    if organization == SPARK_ORGANIZATION {
+        return Ok(None);
+    }
+
+    let artifact = if cross_version {
+        let scala_version = scala_version.context("Can not determine the Scala version of the cluster runtime")?;
+        format!("{}_{}", name, scala_version)
+    } else {
+        name.to_string()
+    };
+    Ok(Some(format!("{}:{}:{}", organization, artifact, version)))
+}
+
+/// Scala binary version of a runtime like `15.4.x-scala2.12`
+fn runtime_scala_version(spark_version: &str) -> Option<&str> {
+    spark_version.split_once("-scala").map(|(_, version)| version)
+}
+
+/// Removes the version from Maven coordinates
+fn module(coordinates: &str) -> &str {
+    coordinates.rsplit_once(':').map(|(module, _)| module).unwrap_or(coordinates)
+}
+
+/// Compares the dependencies declared in Scala files with the Maven libraries of the selected cluster and installs the missing ones
+pub async fn sync(files: Vec<String>, dry_run: bool, wait: bool) -> Result<()> {
+    let cwd = PathBuf::from(&crate::CONTEXT.get().unwrap().cwd);
+    let mut paths = files.iter().map(|file| cwd.join(file)).collect::<Vec<PathBuf>>();
+    if paths.is_empty() {
+        find_scala_files(&cwd, &mut paths)?;
+    }
+
+    let cluster_id = ClusterConfig::read_current().await?.id;
+    let spark_version = crate::client::cluster::get_info(cluster_id.to_owned()).await?.spark_version.unwrap_or_default();
+    let scala_version = runtime_scala_version(&spark_version);
+
+    let mut declared: Vec<String> = Vec::new();
+    for path in paths {
+        for dep in crate::directives::using_deps(&path)? {
+            let coordinates = to_maven_coordinates(&dep, scala_version).map_err(|e| anyhow::anyhow!("{} in `{}`", e, path.display()))?;
+            if let Some(coordinates) = coordinates
+                && !declared.contains(&coordinates)
+            {
+                declared.push(coordinates);
+            }
+        }
+    }
+
+    let installed = crate::client::library::cluster_status(cluster_id.to_owned()).await?.library_statuses
+        .into_iter()
+        .filter_map(|status| status.library.get("maven")?.get("coordinates")?.as_str().map(str::to_string))
+        .collect::<Vec<String>>();
+
+    let mut missing = Vec::new();
+    let mut conflicts = Vec::new();
+    for coordinates in declared.iter().filter(|coordinates| !installed.contains(coordinates)) {
+        let other_version = installed.iter().find(|installed| module(installed) == module(coordinates));
+        if let Some(other_version) = other_version {
+            conflicts.push(VersionConflict { declared: coordinates.to_owned(), installed: other_version.to_owned() });
+        }
+        missing.push(coordinates.to_owned());
+    }
+
+    if !dry_run && !missing.is_empty() {
+        if !conflicts.is_empty() {
+            eprintln!("Warning: some dependencies are installed in another version, both versions will be on the cluster classpath");
+        }
+        let libraries = missing.iter()
+            .map(|coordinates| Library::Maven { coordinates: coordinates.to_owned(), repo: None })
+            .collect::<Vec<Library>>();
+        crate::commands::library::install_libraries(cluster_id, libraries, wait).await?;
+    }
+
+    let result_json = serde_json::to_string(&DepsSummary { declared, missing, conflicts })?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_scala_cli_dependencies_to_maven_coordinates() {
+        assert_eq!(to_maven_coordinates("com.lihaoyi::os-lib:0.9.1", Some("2.12")).unwrap(), Some("com.lihaoyi:os-lib_2.12:0.9.1".to_string()));
+        assert_eq!(to_maven_coordinates("com.google.guava:guava:33.0.0-jre", None).unwrap(), Some("com.google.guava:guava:33.0.0-jre".to_string()));
+        assert_eq!(to_maven_coordinates("org.apache.spark::spark-sql:3.5.0", Some("2.12")).unwrap(), None);
+        assert!(to_maven_coordinates("com.lihaoyi:::os-lib:0.9.1", Some("2.12")).is_err());
+        assert!(to_maven_coordinates("com.lihaoyi::os-lib:0.9.1", None).is_err());
+        assert!(to_maven_coordinates("os-lib", Some("2.12")).is_err());
+    }
+
+    #[test]
+    fn reads_scala_version_of_runtime() {
+        assert_eq!(runtime_scala_version("15.4.x-scala2.12"), Some("2.12"));
+        assert_eq!(runtime_scala_version("15.4.x-gpu-ml-scala2.12"), Some("2.12"));
+        assert_eq!(runtime_scala_version("custom"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_not_follow_symlinked_directories() {
+        let dir = std::env::temp_dir().join("brichka-tests").join(format!("deps-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src").join("main.sc"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("src").join("loop")).unwrap();
+
+        let mut files = Vec::new();
+        find_scala_files(&dir, &mut files).unwrap();
+        assert_eq!(files, vec![dir.join("src").join("main.sc")]);
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{cli::RunArgs, commands::run::CellSource, config::{ClusterConfig, RunHistory, RunRecord}};
+
+const PREVIEW_LENGTH: usize = 80;
+
+#[derive(Serialize)]
+struct RunSummary<'a> {
+    id: u64,
+    timestamp: u64,
+    source_hash: &'a str,
+    preview: String,
+    language: String,
+    cluster_id: Option<&'a str>,
+    warehouse_id: Option<&'a str>,
+    context: Option<&'a str>,
+    command_id: &'a str,
+    duration_ms: u64,
+    result_type: &'a str,
+    result_path: Option<&'a str>,
+}
+
+/// First non-empty line of the source, shortened to fit in a list
+fn preview(source: &str) -> String {
+    let line = source.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    if line.chars().count() > PREVIEW_LENGTH {
+        format!("{}…", line.chars().take(PREVIEW_LENGTH).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+fn format_age(timestamp: u64) -> String {
+    let seconds = crate::config::unix_timestamp().saturating_sub(timestamp);
+    match seconds {
+        0..60 => format!("{}s ago", seconds),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+fn summarize(record: &RunRecord) -> RunSummary<'_> {
+    RunSummary {
+        id: record.id,
+        timestamp: record.timestamp,
+        source_hash: &record.source_hash,
+        preview: preview(&record.source),
+        language: record.language.to_string(),
+        cluster_id: record.cluster_id.as_deref(),
+        warehouse_id: record.warehouse_id.as_deref(),
+        context: record.context.as_deref(),
+        command_id: &record.command_id,
+        duration_ms: record.duration_ms,
+        result_type: &record.result_type,
+        result_path: record.result_path.as_deref(),
+    }
+}
+
+/// Returns the run with the given id, or lets the user pick one, most recent first
+async fn select_run(id: Option<u64>) -> Result<RunRecord> {
+    if let Some(id) = id {
+        return RunHistory::find_local(id).await;
+    }
+
+    let mut records = RunHistory::read_local().await?;
+    if records.is_empty() {
+        anyhow::bail!("Run history is empty");
+    }
+    records.reverse();
+
+    let items: String = records
+        .iter()
+        .map(|r| {
+            let target = r.warehouse_id.as_deref().or(r.cluster_id.as_deref()).unwrap_or_default();
+            format!("#{} {} [{}] {} {} ({})", r.id, format_age(r.timestamp), r.result_type, r.language, preview(&r.source), target)
+        })
+        .collect::<Vec<String>>().join("\n");
+
+    let selected_index = crate::commands::config::run_skim(&items, "Select run: ")?;
+    Ok(records.swap_remove(selected_index))
+}
+
+pub async fn list(limit: Option<usize>) -> Result<()> {
+    let records = RunHistory::read_local().await?;
+    let skipped = limit.map(|limit| records.len().saturating_sub(limit)).unwrap_or_default();
+    let summaries = records.iter().skip(skipped).map(summarize).collect::<Vec<RunSummary>>();
+
+    let result_json = serde_json::to_string(&summaries)?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+pub async fn show(id: Option<u64>) -> Result<()> {
+    let record = select_run(id).await?;
+    let result_json = serde_json::to_string(&record)?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+/// Runs the recorded code again on the same kind of target and in the same shared context
+pub async fn rerun(id: Option<u64>, start: bool) -> Result<()> {
+    let record = select_run(id).await?;
+
+    if let Some(cluster_id) = record.cluster_id.as_deref()
+        && let Ok(configured) = ClusterConfig::read_current().await
+        && configured.id != cluster_id
+    {
+        eprintln!("Warning: run #{} was executed on cluster `{}`, rerunning on the configured cluster `{}`", record.id, cluster_id, configured.id);
+    }
+
+    // The recorded code already has its directives and variables applied
+    let args = RunArgs {
+        command: None,
+        file: None,
+        language: Some(record.language),
+        init: false,
+        replay: false,
+        context: record.context,
+        start,
+        using_files: false,
+        vars: Vec::new(),
+        warehouse: record.warehouse_id.is_some(),
+        external_links: false,
+        parallel_downloads: RunArgs::DEFAULT_PARALLEL_DOWNLOADS,
+    };
+    let source = CellSource::Prepared { code: record.source, file: record.file };
+    crate::commands::run::run_source(source, record.language, args).await
+}
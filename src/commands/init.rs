@@ -1,17 +1,27 @@
-
-
-use crate::config::{ClusterConfig, ContextConfig};
+use crate::{config::{ClusterConfig, ContextConfig, ContextHistory}, language::Language};
 
 use anyhow::Result;
 
-pub async fn init() -> Result<()> {
-    let cluster = ClusterConfig::read_local().await.or(ClusterConfig::read_global().await)?;
+pub async fn init(name: String, language: Language) -> Result<()> {
+    ContextConfig::validate_name(&name)?;
+    let cluster = ClusterConfig::read_current().await?;
     let cluster_id = cluster.id;
-    let context_id = crate::client::context::create(cluster_id.clone(), "sql".to_string()).await?.id;
+    let previous_context = ContextConfig::read_local(&name).await.ok();
+
+    let context_id = crate::commands::create_context(cluster_id.clone(), language).await?;
+    ContextConfig::new(name.to_owned(), context_id.to_owned(), cluster_id.to_owned(), language).write_local().await?;
+    // Cells executed in the replaced context are not defined in the new one
+    ContextHistory::clear_local(&name).await?;
 
-    ContextConfig::new(context_id.to_owned()).write_local().await?;
-    
-    crate::commands::await_context(cluster_id.to_owned(), context_id.to_owned()).await?;
+    // The replaced context is not referenced anymore, free it on the cluster
+    if let Some(previous_context) = previous_context {
+        let previous_cluster_id = previous_context.cluster_id.unwrap_or(cluster_id);
+        if let Err(e) = crate::commands::destroy_context(previous_cluster_id, previous_context.id.to_owned()).await
+            && crate::CONTEXT.get().unwrap().debug
+        {
+            eprintln!("Failed to destroy the previous execution context `{}`: {}", previous_context.id, e);
+        }
+    }
 
     Ok(())
 }
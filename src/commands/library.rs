@@ -0,0 +1,160 @@
+use anyhow::Result;
+use tokio::time::{ sleep, Duration, Instant };
+
+use crate::{cli::LibraryArgs, client::library::{Library, LibraryFullStatus}, config::ClusterConfig, progress::ProgressEvent};
+
+fn libraries_from_args(args: LibraryArgs, repo: Option<String>) -> Vec<Library> {
+    let maven = args.maven.into_iter().map(|coordinates| Library::Maven { coordinates, repo: repo.to_owned() });
+    let pypi = args.pypi.into_iter().map(|package| Library::Pypi { package, repo: repo.to_owned() });
+    let jars = args.jar.into_iter().map(Library::Jar);
+    let wheels = args.whl.into_iter().map(Library::Whl);
+    maven.chain(pypi).chain(jars).chain(wheels).collect()
+}
+
+/// Polls after which a library without a status is treated as lost
+const MISSING_STATUS_POLLS: u32 = 5;
+
+/// Longest time `--wait` waits for libraries to be installed
+const INSTALL_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Library type (`maven`, `pypi`, `jar` or `whl`) and its coordinates, package or path.
+/// Other fields, such as the repository, may be normalized by the API and are ignored
+fn identity(library: &serde_json::Value) -> Option<(&str, &str)> {
+    let (kind, spec) = library.as_object()?.iter().next()?;
+    let label = match kind.as_str() {
+        "maven" => spec.get("coordinates")?.as_str()?,
+        "pypi" => spec.get("package")?.as_str()?,
+        _ => spec.as_str()?,
+    };
+    Some((kind.as_str(), label.trim()))
+}
+
+/// Maven coordinates, package name or path of the library
+fn label(library: &serde_json::Value) -> String {
+    let label = library.get("maven").and_then(|maven| maven.get("coordinates"))
+        .or(library.get("pypi").and_then(|pypi| pypi.get("package")))
+        .or(library.get("jar"))
+        .or(library.get("whl"))
+        .and_then(|value| value.as_str());
+    match label {
+        Some(label) => label.to_string(),
+        None => library.to_string(),
+    }
+}
+
+fn find_status<'a>(statuses: &'a [LibraryFullStatus], library: &serde_json::Value) -> Option<&'a LibraryFullStatus> {
+    let matches = |status: &&LibraryFullStatus| match (identity(&status.library), identity(library)) {
+        (Some((kind, label)), Some((expected_kind, expected_label))) => kind == expected_kind && label.eq_ignore_ascii_case(expected_label),
+        _ => status.library == *library,
+    };
+    statuses.iter().find(matches)
+}
+
+pub async fn list() -> Result<()> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+    let statuses = crate::client::library::cluster_status(cluster_id).await?.library_statuses;
+    let result_json = serde_json::to_string(&statuses)?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+/// Polls the library statuses until every library is installed, reporting status changes as progress
+async fn await_installed(cluster_id: String, libraries: &[serde_json::Value]) -> Result<()> {
+    let started = Instant::now();
+    let mut last_statuses: Vec<Option<String>> = vec![None; libraries.len()];
+    let mut polls = 0;
+    loop {
+        polls += 1;
+        let statuses = crate::client::library::cluster_status(cluster_id.to_owned()).await?.library_statuses;
+
+        let mut installed = 0;
+        for (library, last_status) in libraries.iter().zip(last_statuses.iter_mut()) {
+            let Some(status) = find_status(&statuses, library) else {
+                if polls >= MISSING_STATUS_POLLS {
+                    anyhow::bail!("The cluster does not report a status for `{}`. Check `brichka cluster libraries list`", label(library));
+                }
+                continue;
+            };
+            if last_status.as_deref() != Some(status.status.as_str()) {
+                crate::progress::report(ProgressEvent::Library {
+                    library: &label(library),
+                    status: &status.status,
+                    elapsed_secs: started.elapsed().as_secs(),
+                });
+                *last_status = Some(status.status.to_owned());
+            }
+
+            match status.status.as_str() {
+                "INSTALLED" => installed += 1,
+                "FAILED" => anyhow::bail!("Failed to install `{}`: {}", label(library), status.messages.join("; ")),
+                "SKIPPED" => anyhow::bail!("Installation of `{}` was skipped: {}", label(library), status.messages.join("; ")),
+                _ => {},
+            }
+        }
+
+        if installed == libraries.len() {
+            break;
+        } else if started.elapsed() >= INSTALL_TIMEOUT {
+            anyhow::bail!(
+                "Libraries are still not installed after {} minutes, stopped waiting. Check `brichka cluster libraries list`",
+                INSTALL_TIMEOUT.as_secs() / 60
+            );
+        }
+        sleep(Duration::from_secs(2)).await;
+    };
+    Ok(())
+}
+
+pub async fn install(args: LibraryArgs, repo: Option<String>, wait: bool) -> Result<()> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+    install_libraries(cluster_id, libraries_from_args(args, repo), wait).await
+}
+
+pub async fn install_libraries(cluster_id: String, libraries: Vec<Library>, wait: bool) -> Result<()> {
+    // Libraries can only be awaited on a running cluster, a terminated one installs them on start
+    if wait {
+        crate::commands::run::check_cluster_state(cluster_id.to_owned(), false).await?;
+    }
+    crate::client::library::install(cluster_id.to_owned(), &libraries).await?;
+
+    if wait {
+        let libraries = libraries.iter().map(serde_json::to_value).collect::<Result<Vec<serde_json::Value>, _>>()?;
+        await_installed(cluster_id, &libraries).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn uninstall(args: LibraryArgs, repo: Option<String>) -> Result<()> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+    let libraries = libraries_from_args(args, repo);
+
+    crate::client::library::uninstall(cluster_id, &libraries).await?;
+    eprintln!("Libraries are removed when the cluster restarts. Restart it with `brichka cluster restart`");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(library: serde_json::Value) -> LibraryFullStatus {
+        LibraryFullStatus { library, status: "INSTALLED".to_string(), messages: Vec::new(), is_library_for_all_clusters: false }
+    }
+
+    #[test]
+    fn finds_status_by_coordinates_ignoring_normalized_fields() {
+        let statuses = vec![
+            status(serde_json::json!({"maven": {"coordinates": "com.example:lib_2.12:1.0.0", "repo": "https://repo.example.com/"}})),
+            status(serde_json::json!({"pypi": {"package": "requests==2.32.0"}})),
+        ];
+        let maven = serde_json::to_value(Library::Maven { coordinates: "com.example:lib_2.12:1.0.0".to_string(), repo: Some("https://repo.example.com".to_string()) }).unwrap();
+        assert!(find_status(&statuses, &maven).is_some());
+
+        let pypi = serde_json::to_value(Library::Pypi { package: "requests==2.32.0".to_string(), repo: None }).unwrap();
+        assert!(find_status(&statuses, &pypi).is_some());
+
+        let jar = serde_json::to_value(Library::Jar("com.example:lib_2.12:1.0.0".to_string())).unwrap();
+        assert!(find_status(&statuses, &jar).is_none());
+    }
+}
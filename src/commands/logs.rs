@@ -0,0 +1,157 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use tokio::time::{ sleep, Duration };
+
+use crate::{cli::LogFileArgs, client::{cluster::ClusterLogConf, files::{FileStatus, MAX_READ_BYTES, RemoteFile}}, config::ClusterConfig};
+
+/// Databricks delivers the logs every few minutes, polling more often only repeats empty reads
+const FOLLOW_INTERVAL_SECS: u64 = 10;
+/// Number of printed bytes kept to tell an appended log from a rotated one
+const TAIL_BYTES: usize = 256;
+
+/// Path of the driver log file below the log delivery destination of the cluster
+fn driver_log_file(cluster_id: &str, conf: &ClusterLogConf, file_name: &str) -> Result<RemoteFile> {
+    let path = |destination: &str| format!("{}/{}/driver/{}", destination.trim_end_matches('/'), cluster_id, file_name);
+    if let Some(dbfs) = &conf.dbfs {
+        Ok(RemoteFile::Dbfs(path(dbfs.destination.trim_start_matches("dbfs:"))))
+    } else if let Some(volumes) = &conf.volumes {
+        Ok(RemoteFile::Volume(path(&volumes.destination)))
+    } else if let Some(s3) = &conf.s3 {
+        anyhow::bail!("Logs are delivered to `{}`, only DBFS and Volume destinations can be read", s3.destination)
+    } else {
+        anyhow::bail!("Cluster has no supported log delivery destination")
+    }
+}
+
+/// Offset in `data` where its last `lines` lines start. A `partial` first line is never included
+fn last_lines_start(data: &[u8], lines: usize, partial: bool) -> usize {
+    if lines == 0 {
+        return data.len();
+    }
+    let content = data.strip_suffix(b"\n").unwrap_or(data);
+    let first_line_end = || if partial {
+        content.iter().position(|byte| *byte == b'\n').map(|position| position + 1).unwrap_or(data.len())
+    } else {
+        0
+    };
+    content.iter().enumerate().rev()
+        .filter(|(_, byte)| **byte == b'\n')
+        .nth(lines - 1)
+        .map(|(position, _)| position + 1)
+        .unwrap_or_else(first_line_end)
+}
+
+fn print_bytes(data: &[u8]) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(data)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Position in a followed log
+struct Followed {
+    offset: u64,
+    modified: Option<String>,
+    /// Last printed bytes, ending at `offset`
+    tail: Vec<u8>,
+}
+
+impl Followed {
+    fn advance(&mut self, data: &[u8]) {
+        self.offset += data.len() as u64;
+        self.tail.extend_from_slice(data);
+        let excess = self.tail.len().saturating_sub(TAIL_BYTES);
+        self.tail.drain(..excess);
+    }
+
+    /// A log is rotated when it shrinks, or when it was modified and no longer contains the printed bytes
+    async fn is_rotated(&self, file: &RemoteFile, status: &FileStatus) -> Result<bool> {
+        if status.size < self.offset {
+            return Ok(true);
+        }
+        if status.modified == self.modified || self.tail.is_empty() {
+            return Ok(false);
+        }
+        let tail_start = self.offset - self.tail.len() as u64;
+        Ok(file.read(tail_start, self.tail.len() as u64).await? != self.tail)
+    }
+
+    /// Prints what was appended to the log since the previous poll
+    async fn poll(&mut self, file: &RemoteFile) -> Result<()> {
+        let status = file.status().await?;
+        if self.is_rotated(file, &status).await? {
+            eprintln!("Log `{}` was rotated, reading it from the beginning", file.path());
+            self.offset = 0;
+            self.tail.clear();
+        }
+        self.modified = status.modified;
+        while self.offset < status.size {
+            let data = file.read(self.offset, status.size - self.offset).await?;
+            if data.is_empty() {
+                break;
+            }
+            print_bytes(&data)?;
+            self.advance(&data);
+        }
+        Ok(())
+    }
+}
+
+/// Prints the last `lines` lines of a driver log of the selected cluster and, with `follow`, polls it for new output
+pub async fn logs(file: LogFileArgs, follow: bool, lines: usize) -> Result<()> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+    let info = crate::client::cluster::get_info(cluster_id.to_owned()).await?;
+    let conf = info.cluster_log_conf.context(
+        "Cluster has no log delivery configured. Set a DBFS or Volume destination in the logging settings of the cluster"
+    )?;
+    let log_file = driver_log_file(&cluster_id, &conf, file.file_name())?;
+
+    let status = log_file.status().await.with_context(|| format!(
+        "Failed to read `{}`. Logs are delivered every few minutes, a recently started cluster may not have delivered them yet",
+        log_file.path()
+    ))?;
+    let tail_start = status.size.saturating_sub(MAX_READ_BYTES);
+    let tail = log_file.read(tail_start, status.size - tail_start).await?;
+    print_bytes(&tail[last_lines_start(&tail, lines, tail_start > 0)..])?;
+
+    if !follow {
+        return Ok(());
+    }
+
+    let mut followed = Followed { offset: tail_start, modified: status.modified, tail: Vec::new() };
+    followed.advance(&tail);
+    loop {
+        sleep(Duration::from_secs(FOLLOW_INTERVAL_SECS)).await;
+        // Reads fail now and then while the log is delivered, the next poll continues where this one stopped
+        if let Err(e) = followed.poll(&log_file).await {
+            eprintln!("Warning: failed to read `{}`, retrying: {:#}", log_file.path(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_start_of_last_lines() {
+        let data = b"one\ntwo\nthree\n";
+        assert_eq!(&data[last_lines_start(data, 2, false)..], b"two\nthree\n");
+        assert_eq!(&data[last_lines_start(data, 5, false)..], data);
+        assert_eq!(&data[last_lines_start(data, 5, true)..], b"two\nthree\n");
+        assert_eq!(last_lines_start(data, 0, false), data.len());
+    }
+
+    #[test]
+    fn keeps_only_the_last_printed_bytes() {
+        let mut followed = Followed { offset: 100, modified: None, tail: Vec::new() };
+        followed.advance(b"abc");
+        assert_eq!((followed.offset, followed.tail.as_slice()), (103, b"abc".as_slice()));
+
+        let data = vec![b'x'; TAIL_BYTES];
+        followed.advance(&data);
+        assert_eq!(followed.offset, 103 + TAIL_BYTES as u64);
+        assert_eq!(followed.tail, data);
+    }
+}
@@ -62,7 +62,7 @@ pub struct Backend {
 //TODO: Rewrite completions
 impl Backend {
     fn new (client: Client) -> Self {
-        Backend { client: client, cache: Arc::new(Cache{catalogs: DashMap::new()}), documents: Arc::new(DashMap::new()) }
+        Backend { client, cache: Arc::new(Cache{catalogs: DashMap::new()}), documents: Arc::new(DashMap::new()) }
     }
 
 
@@ -276,7 +276,7 @@ impl LanguageServer for Backend {
 
 
 pub async fn start() -> Result<()> {
-    let (service, socket) = LspService::new(|client| Backend::new(client));
+    let (service, socket) = LspService::new(Backend::new);
     Server::new(tokio::io::stdin(), tokio::io::stdout(), socket).serve(service).await;
 
     Ok(())
@@ -1,13 +1,43 @@
 pub mod cluster;
 pub mod config;
+pub mod context;
+pub mod deps;
+pub mod history;
+pub mod library;
+pub mod logs;
 pub mod init;
 pub mod status;
 pub mod run;
+pub mod spark;
 pub mod lsp;
+pub mod permissions;
+pub mod warehouse;
 
 use anyhow::Result;
 use tokio::time::{ sleep, Duration };
 
+use crate::{config::{CreatedContext, CreatedContextsConfig}, language::Language};
+
+/// Emoji for a cluster or SQL warehouse state
+pub fn render_state(state: &str) -> &str {
+    match state {
+        "RUNNING" =>  "🟢",
+        "PENDING" => "🔵",
+        "RESTARTING" => "♻️",
+        "RESIZING" => "⚙️",
+        "TERMINATING" => "🔻",
+        "TERMINATED" => "🔴",
+        "STARTING" => "🔵",
+        "STOPPING" => "🔻",
+        "STOPPED" => "🔴",
+        "DELETING" => "🔻",
+        "DELETED" => "⚫",
+        "ERROR" => "⚠️",
+        "UNKNOWN" => "❔",
+        _ => "❔"
+    }
+}
+
 pub async fn await_context(cluster_id: String, context_id: String) -> Result<()> {
     loop {
         let status = crate::client::context::get_status(cluster_id.to_owned(), context_id.to_owned()).await?.status;
@@ -21,3 +51,23 @@ pub async fn await_context(cluster_id: String, context_id: String) -> Result<()>
     Ok(())
 }
 
+/// Creates an execution context and waits until it is ready. The context is recorded so `brichka context gc` can destroy it if it is leaked
+pub async fn create_context(cluster_id: String, language: Language) -> Result<String> {
+    let context_id = crate::client::context::create(cluster_id.to_owned(), language).await
+        .map_err(|e| crate::commands::permissions::explain_denied(e, &cluster_id, "attach to", "CAN_ATTACH_TO"))?
+        .id;
+    CreatedContextsConfig::add(CreatedContext::new(cluster_id.to_owned(), context_id.to_owned())).await?;
+    await_context(cluster_id, context_id.to_owned()).await?;
+    Ok(context_id)
+}
+
+/// Destroys the context and stops tracking it. A context that is already gone, e.g. after a cluster restart, is only forgotten
+pub async fn destroy_context(cluster_id: String, context_id: String) -> Result<()> {
+    if let Err(e) = crate::client::context::destroy(cluster_id.to_owned(), context_id.to_owned()).await {
+        let gone = crate::client::context::exists(cluster_id.to_owned(), context_id.to_owned()).await.is_ok_and(|exists| !exists);
+        if !gone {
+            return Err(e);
+        }
+    }
+    CreatedContextsConfig::remove(&cluster_id, &context_id).await
+}
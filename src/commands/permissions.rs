@@ -0,0 +1,133 @@
+use tokio::task::JoinSet;
+
+use crate::client::{cluster::{Cluster, GetClusterInfoResponse}, scim::CurrentUser};
+
+const ADMINS_GROUP: &str = "admins";
+/// Permission reads the picker sends at the same time
+const MAX_CONCURRENT_READS: usize = 8;
+
+/// What the current user can do with a cluster, as far as it can be told before using it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterAccess {
+    /// Single user cluster of another user or group
+    None,
+    /// `CAN_MANAGE`, the user can attach to, start and restart the cluster
+    Manage,
+}
+
+/// Access that follows from the cluster alone, without reading its permissions
+fn known_access(
+    data_security_mode: Option<&str>,
+    single_user_name: Option<&str>,
+    creator_user_name: Option<&str>,
+    user: &CurrentUser,
+) -> Option<ClusterAccess> {
+    let is_user_or_group = |name: &str| name == user.user_name || user.groups.iter().any(|group| group.display == name);
+    // Single user clusters can only be attached to by their user or group, regardless of the permissions
+    if data_security_mode == Some("SINGLE_USER") && single_user_name.is_some_and(|name| !is_user_or_group(name)) {
+        Some(ClusterAccess::None)
+    } else if is_user_or_group(ADMINS_GROUP) || creator_user_name == Some(user.user_name.as_str()) {
+        Some(ClusterAccess::Manage)
+    } else {
+        None
+    }
+}
+
+/// The permissions of a cluster are only readable with `CAN_MANAGE`, other users may still attach to it or restart it
+async fn read_access(cluster_id: String) -> Option<ClusterAccess> {
+    crate::client::permissions::can_manage_cluster(cluster_id).await.ok()?.then_some(ClusterAccess::Manage)
+}
+
+/// Access of the user to each cluster, `None` where it can't be told. The permissions are only read for
+/// clusters whose access doesn't follow from the cluster list, a few at a time
+pub async fn cluster_accesses(user: &CurrentUser, clusters: &[Cluster]) -> Vec<Option<ClusterAccess>> {
+    let mut accesses = clusters.iter()
+        .map(|c| known_access(c.data_security_mode.as_deref(), c.single_user_name.as_deref(), c.creator_user_name.as_deref(), user))
+        .collect::<Vec<Option<ClusterAccess>>>();
+    let mut unknown = accesses.iter().enumerate()
+        .filter(|(_, access)| access.is_none())
+        .map(|(index, _)| index)
+        .collect::<Vec<usize>>()
+        .into_iter();
+
+    let mut reads = JoinSet::new();
+    loop {
+        while reads.len() < MAX_CONCURRENT_READS && let Some(index) = unknown.next() {
+            let cluster_id = clusters[index].id.to_owned();
+            reads.spawn(async move { (index, read_access(cluster_id).await) });
+        }
+        let Some(Ok((index, access))) = reads.join_next().await else {
+            break;
+        };
+        accesses[index] = access;
+    }
+    accesses
+}
+
+/// Access of the current user to the cluster, `None` if it can't be told.
+/// The current user is only looked up for single user clusters, where the access is known without it being readable
+pub async fn cluster_access(info: &GetClusterInfoResponse) -> Option<ClusterAccess> {
+    if info.data_security_mode.as_deref() != Some("SINGLE_USER") {
+        return None;
+    }
+    let user = crate::client::scim::me().await.ok()?;
+    known_access(info.data_security_mode.as_deref(), info.single_user_name.as_deref(), None, &user)
+        .filter(|access| *access == ClusterAccess::None)
+}
+
+fn is_permission_denied(error: &anyhow::Error) -> bool {
+    let message = error.to_string();
+    let lowercase = message.to_lowercase();
+    message.contains("PERMISSION_DENIED") || (lowercase.contains("does not have") && lowercase.contains("permission"))
+}
+
+/// Replaces a permission error of the API with one that names the missing cluster permission
+pub fn explain_denied(error: anyhow::Error, cluster_id: &str, action: &str, permission: &str) -> anyhow::Error {
+    if is_permission_denied(&error) {
+        anyhow::anyhow!("You don't have permission to {} cluster `{}`. Ask its owner for `{}` ({})", action, cluster_id, permission, error)
+    } else {
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::scim::Group;
+
+    fn user() -> CurrentUser {
+        CurrentUser { user_name: "ada@example.com".to_string(), groups: vec![Group { display: "data".to_string() }] }
+    }
+
+    #[test]
+    fn single_user_clusters_accept_only_their_user_or_group() {
+        let user = user();
+        assert_eq!(known_access(Some("SINGLE_USER"), Some("bob@example.com"), None, &user), Some(ClusterAccess::None));
+        assert_eq!(known_access(Some("SINGLE_USER"), Some("ada@example.com"), None, &user), None);
+        assert_eq!(known_access(Some("SINGLE_USER"), Some("data"), None, &user), None);
+        assert_eq!(known_access(Some("USER_ISOLATION"), Some("bob@example.com"), None, &user), None);
+        assert_eq!(known_access(None, None, None, &user), None);
+    }
+
+    #[test]
+    fn creators_and_admins_manage_clusters() {
+        let user = user();
+        assert_eq!(known_access(None, None, Some("ada@example.com"), &user), Some(ClusterAccess::Manage));
+        assert_eq!(known_access(None, None, Some("bob@example.com"), &user), None);
+
+        let admin = CurrentUser { user_name: "bob@example.com".to_string(), groups: vec![Group { display: ADMINS_GROUP.to_string() }] };
+        assert_eq!(known_access(None, None, None, &admin), Some(ClusterAccess::Manage));
+    }
+
+    #[test]
+    fn explains_only_permission_errors() {
+        let denied = anyhow::anyhow!("PERMISSION_DENIED: User ada does not have Restart permissions on cluster 0101-abc");
+        assert!(explain_denied(denied, "0101-abc", "start", "CAN_RESTART").to_string().starts_with("You don't have permission to start"));
+
+        let denied = anyhow::anyhow!("User ada does not have Attach permissions on cluster 0101-abc");
+        assert!(explain_denied(denied, "0101-abc", "attach to", "CAN_ATTACH_TO").to_string().contains("CAN_ATTACH_TO"));
+
+        let other = anyhow::anyhow!("INVALID_STATE: Cluster 0101-abc is in unexpected state Pending");
+        assert_eq!(explain_denied(other, "0101-abc", "start", "CAN_RESTART").to_string(), "INVALID_STATE: Cluster 0101-abc is in unexpected state Pending");
+    }
+}
@@ -1,85 +1,102 @@
 
-use std::env::{self};
+use std::{collections::BTreeMap, env::{self}, path::{Path, PathBuf}, sync::Arc};
 
 use anyhow::{Context, Result};
 use serde::Serialize;
-use tokio::{fs, time::{ Duration, sleep }};
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::{AsyncWriteExt, BufWriter}, task::JoinSet, time::{ Duration, Instant, sleep }};
 
-use crate::{client::{command::{GetCommandInfoResponse, Schema}, context::GetContextStatusResponse}, config::{ClusterConfig, ContextConfig}};
+use clap_stdin::MaybeStdin;
 
-async fn create_temporary_context(cluster_id: String) -> Result<String> {
-    let context_id = crate::client::context::create(cluster_id.clone(), "sql".to_string()).await?.id;
-    crate::commands::await_context(cluster_id.to_owned(), context_id.to_owned()).await?;
-    Ok(context_id)
+use crate::{cli::RunArgs, client::{cluster::GetClusterInfoResponse, command::{GetCommandInfoResponse, Schema}, context::GetContextStatusResponse, statement::{Column, Disposition, ExternalLink, StatementResponse}}, commands::{permissions::ClusterAccess, spark::SparkProgress}, config::{ClusterConfig, ContextConfig, ContextHistory, ExecutedCell, RunHistory, RunRecord, VarsConfig, WarehouseConfig}, directives::Cell, language::Language, progress::ProgressEvent};
+
+async fn create_temporary_context(cluster_id: String, language: Language) -> Result<String> {
+    crate::commands::create_context(cluster_id, language).await
+}
+
+enum ExecutionContext {
+    Shared(ContextConfig),
+    Temporary(String),
+}
+
+impl ExecutionContext {
+    fn id(&self) -> String {
+        match self {
+            ExecutionContext::Shared(context) => context.id.to_owned(),
+            ExecutionContext::Temporary(id) => id.to_owned(),
+        }
+    }
 }
 
-async fn get_or_create_context(cluster_id: String, init: bool) -> Result<String> {
-    let existing_context = ContextConfig::read_local().await;
+async fn init_shared_context(name: &str, language: Language, replay: bool) -> Result<ExecutionContext> {
+    if replay {
+        let context = crate::commands::context::replay_history(name, language).await?;
+        return Ok(ExecutionContext::Shared(context));
+    }
+    crate::commands::init::init(name.to_string(), language).await?;
+    let context = ContextConfig::read_local(name).await?;
+    Ok(ExecutionContext::Shared(context))
+}
+
+/// Returns the shared context with the given name, or the default one if no name is given.
+/// Without `init` a missing default context is replaced with a temporary one-off context.
+/// New contexts are created in the language of the cell, recreated ones keep their previous language
+async fn get_or_create_context(cluster_id: String, name: Option<String>, init: bool, replay: bool, language: Language) -> Result<ExecutionContext> {
+    let context_name = name.to_owned().unwrap_or(ContextConfig::DEFAULT_NAME.to_string());
+    let existing_context = ContextConfig::read_local(&context_name).await;
     match existing_context {
         Err(_) => {
             if init {
-                crate::commands::init::init().await?;
-                let context = ContextConfig::read_local().await?;
-                Ok(context.id)
+                init_shared_context(&context_name, language, replay).await
+            } else if name.is_none() {
+                Ok(ExecutionContext::Temporary(create_temporary_context(cluster_id, language).await?))
             } else {
-                create_temporary_context(cluster_id).await
+                anyhow::bail!("Execution context `{}` does not exist. Create it with `brichka init --name {}`", context_name, context_name)
             }
         },
-        Ok(context) =>  {
-            let response = crate::client::context::get_status(cluster_id, context.id.to_owned()).await;
-            if let Ok(GetContextStatusResponse { ref status }) = response 
-                && status == "Running" 
+        Ok(context) => {
+            let context_language = context.language.unwrap_or(language);
+            if let Some(context_cluster_id) = context.cluster_id.as_ref()
+                && *context_cluster_id != cluster_id
             {
-                Ok(context.id)
-            } else {
                 if init {
-                    crate::commands::init::init().await?;
-                    let context = ContextConfig::read_local().await?;
-                    Ok(context.id)
-                } else {
-                    anyhow::bail!("Execution context does not exist anymore. Recreate it with `brichka init`")
+                    return init_shared_context(&context_name, context_language, replay).await;
                 }
+                anyhow::bail!(
+                    "Execution context `{}` belongs to cluster `{}`, but the selected cluster is `{}`. Recreate it with `brichka init --name {}`",
+                    context_name, context_cluster_id, cluster_id, context_name
+                );
             }
-        }
-    }
-}
 
-fn filter_excluded_sections(command: &str) -> String {
-    let mut result = Vec::new();
-    let mut excluding = false;
-    for line in command.lines() {
-        let trimmed = line.trim();
-
-        match trimmed {
-            "// brichka: exclude" => {
-                excluding = true;
-                continue;
-            },
-            "// brichka: include" => {
-                excluding = false;
-                continue;
-            },
-            _ => {
-                if !excluding {
-                    result.push(line);
-                };
+            let response = crate::client::context::get_status(cluster_id, context.id.to_owned()).await;
+            if let Ok(GetContextStatusResponse { ref status }) = response 
+                && status == "Running" 
+            {
+                Ok(ExecutionContext::Shared(context))
+            } else if init {
+                init_shared_context(&context_name, context_language, replay).await
+            } else {
+                anyhow::bail!("Execution context `{}` does not exist anymore. Recreate it with `brichka init --name {}`", context_name, context_name)
             }
         }
-
     }
-    result.join("\n")
 }
 
-
-
-async fn await_command_result(cluster_id: String, context_id: String, command_id: String) -> Result<GetCommandInfoResponse> {
+/// Polls the command until it is finished. With `progress` the Spark jobs of the command are reported while it runs
+async fn await_command_result(cluster_id: String, context_id: String, command_id: String, mut progress: Option<SparkProgress>) -> Result<GetCommandInfoResponse> {
     loop {
         let result = crate::client::command::get_info(command_id.to_owned(), cluster_id.to_owned(), context_id.to_owned()).await?;
 
 
         if result.status == "Finished" || result.status == "Error" || result.status == "Cancelled" {
+            if let Some(progress) = progress.as_mut() {
+                progress.finish().await;
+            }
             return Ok(result);
         } 
+        if let Some(progress) = progress.as_mut() {
+            progress.poll().await;
+        }
         sleep(Duration::from_secs(2)).await;
     };
 
@@ -101,27 +118,54 @@ enum CommandRunResult {
     }
 }
 
+impl CommandRunResult {
+    fn kind(&self) -> &'static str {
+        match self {
+            CommandRunResult::Text { .. } => "text",
+            CommandRunResult::Error { .. } => "error",
+            CommandRunResult::Table { .. } => "table",
+        }
+    }
+
+    fn path(&self) -> Option<String> {
+        match self {
+            CommandRunResult::Table { path } => Some(path.to_owned()),
+            _ => None,
+        }
+    }
+}
+
+/// A command sent to a cluster or SQL warehouse together with its result
+struct Execution {
+    code: String,
+    cluster_id: Option<String>,
+    warehouse_id: Option<String>,
+    context: Option<String>,
+    command_id: String,
+    result: CommandRunResult,
+}
+
 fn is_complex_type(field_type: &str) -> bool {
     let trimmed_type = field_type.trim();
     trimmed_type.starts_with("{") || trimmed_type.starts_with("[")
 }
 
 fn format_array_value(schema: &serde_json::Value, value: &serde_json::Value) -> Result<String> {
-    if let Some(element_type) = schema.get("elementType") {
-        if let Some(array_values) = value.as_array() {
-            let element_type_str = serde_json::to_string(element_type)?;
-
-            if is_complex_type(&element_type_str) {
-                let formatted_elements: Result<Vec<serde_json::Value>> = array_values
-                    .iter()
-                    .map(|elem| {
-                        let formatted_str = format_complex_value(&element_type_str, elem)?;
-                        Ok(serde_json::from_str(&formatted_str)?)
-                    })
-                    .collect();
-
-                return Ok(serde_json::to_string(&formatted_elements?)?);
-            }
+    if let Some(element_type) = schema.get("elementType")
+        && let Some(array_values) = value.as_array()
+    {
+        let element_type_str = serde_json::to_string(element_type)?;
+
+        if is_complex_type(&element_type_str) {
+            let formatted_elements: Result<Vec<serde_json::Value>> = array_values
+                .iter()
+                .map(|elem| {
+                    let formatted_str = format_complex_value(&element_type_str, elem)?;
+                    Ok(serde_json::from_str(&formatted_str)?)
+                })
+                .collect();
+
+            return Ok(serde_json::to_string(&formatted_elements?)?);
         }
     }
 
@@ -129,52 +173,52 @@ fn format_array_value(schema: &serde_json::Value, value: &serde_json::Value) ->
 }
 
 fn format_struct_value(schema: &serde_json::Value, value: &serde_json::Value) -> Result<String> {
-    if let Some(fields) = schema.get("fields").and_then(|f| f.as_array()) {
-        if let Some(array_values) = value.as_array() {
-            let mut obj = serde_json::Map::new();
-            for (i, field) in fields.iter().enumerate() {
-                if let Some(field_name) = field.get("name").and_then(|n| n.as_str()) {
-                    if i < array_values.len() {
-                        let field_value = &array_values[i];
-
-                        if let Some(field_type) = field.get("type") {
-                            let field_type_str = serde_json::to_string(field_type)?;
-                            let formatted_value = if is_complex_type(&field_type_str) {
-                                let formatted_str = format_complex_value(&field_type_str, field_value)?;
-                                serde_json::from_str(&formatted_str)?
-                            } else {
-                                field_value.clone()
-                            };
-                            obj.insert(field_name.to_string(), formatted_value);
-                        } else {
-                            obj.insert(field_name.to_string(), field_value.clone());
-                        }
-                    }
+    if let Some(fields) = schema.get("fields").and_then(|f| f.as_array())
+        && let Some(array_values) = value.as_array()
+    {
+        let mut obj = serde_json::Map::new();
+        for (i, field) in fields.iter().enumerate() {
+            if let Some(field_name) = field.get("name").and_then(|n| n.as_str())
+                && i < array_values.len()
+            {
+                let field_value = &array_values[i];
+
+                if let Some(field_type) = field.get("type") {
+                    let field_type_str = serde_json::to_string(field_type)?;
+                    let formatted_value = if is_complex_type(&field_type_str) {
+                        let formatted_str = format_complex_value(&field_type_str, field_value)?;
+                        serde_json::from_str(&formatted_str)?
+                    } else {
+                        field_value.clone()
+                    };
+                    obj.insert(field_name.to_string(), formatted_value);
+                } else {
+                    obj.insert(field_name.to_string(), field_value.clone());
                 }
             }
-
-            let json_str = serde_json::to_string(&obj)?;
-            // Replace ":" with ": " but only for keys (before values)
-            let formatted = json_str.replace("\":", "\": ");
-            return Ok(formatted);
         }
+
+        let json_str = serde_json::to_string(&obj)?;
+        // Replace ":" with ": " but only for keys (before values)
+        let formatted = json_str.replace("\":", "\": ");
+        return Ok(formatted);
     }
 
     Ok(serde_json::to_string(value)?)
 }
 
 pub fn format_complex_value(type_str: &str, value: &serde_json::Value) -> Result<String> {
-    if let Ok(schema) = serde_json::from_str::<serde_json::Value>(type_str) {
-        if let Some(type_name) = schema.get("type").and_then(|t| t.as_str()) {
-            match type_name {
-                "struct" => {
-                    return format_struct_value(&schema, value);
-                }
-                "array" => {
-                    return format_array_value(&schema, value);
-                }
-                _ => {}
+    if let Ok(schema) = serde_json::from_str::<serde_json::Value>(type_str)
+        && let Some(type_name) = schema.get("type").and_then(|t| t.as_str())
+    {
+        match type_name {
+            "struct" => {
+                return format_struct_value(&schema, value);
             }
+            "array" => {
+                return format_array_value(&schema, value);
+            }
+            _ => {}
         }
     }
 
@@ -237,18 +281,50 @@ fn format_table_result(schema: &[Schema], data: &serde_json::Value) -> Result<Ve
     Ok(result)
 }
 
-async fn write_table_result(command_id: String, result: Vec<serde_json::Value>) -> Result<String> {
-    let temp_dir = env::temp_dir();
-    let path = temp_dir.join("brichka").join("results").join(format!("{}.jsonl", command_id));
-    if let Some(parent) = std::path::Path::new(&path).parent() {
+/// Writes table rows to the result file incrementally, so large results don't have to be kept in memory
+struct TableResultWriter {
+    path: PathBuf,
+    file: BufWriter<fs::File>,
+    rows_written: usize,
+}
+
+impl TableResultWriter {
+    async fn create(command_id: &str) -> Result<TableResultWriter> {
+        let temp_dir = env::temp_dir();
+        let path = temp_dir.join("brichka").join("results").join(format!("{}.jsonl", command_id));
+        if let Some(parent) = std::path::Path::new(&path).parent() {
             fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = BufWriter::new(fs::File::create(&path).await?);
+        file.write_all(b"[").await?;
+
+        Ok(TableResultWriter { path, file, rows_written: 0 })
     }
-    
-    let result_string = serde_json::to_string(&result).context("Failed to serialize table data")?;
 
-    fs::write(path.to_owned(), result_string).await?;
+    async fn write_rows(&mut self, rows: &[serde_json::Value]) -> Result<()> {
+        for row in rows {
+            if self.rows_written > 0 {
+                self.file.write_all(b",").await?;
+            }
+            let row_string = serde_json::to_string(row).context("Failed to serialize table data")?;
+            self.file.write_all(row_string.as_bytes()).await?;
+            self.rows_written += 1;
+        }
+        Ok(())
+    }
 
-    Ok(path.to_string_lossy().to_string())
+    async fn finish(mut self) -> Result<String> {
+        self.file.write_all(b"]").await?;
+        self.file.flush().await?;
+        Ok(self.path.to_string_lossy().to_string())
+    }
+}
+
+async fn write_table_result(command_id: String, result: Vec<serde_json::Value>) -> Result<String> {
+    let mut writer = TableResultWriter::create(&command_id).await?;
+    writer.write_rows(&result).await?;
+    writer.finish().await
 }
 
 async fn format_command_result(result: GetCommandInfoResponse) -> Result<CommandRunResult> {
@@ -273,37 +349,505 @@ async fn format_command_result(result: GetCommandInfoResponse) -> Result<Command
     }
 }
 
+/// Output of a cell that is consumed by brichka itself instead of being printed
+pub enum CellOutput {
+    Text(String),
+    Table(Vec<serde_json::Value>),
+}
+
+/// Runs a cell in an existing execution context. Errors raised by the cell are returned as errors
+pub async fn execute_cell(code: String, language: Language, cluster_id: String, context_id: String) -> Result<CellOutput> {
+    let command_id = crate::client::command::run(code, cluster_id.to_owned(), context_id.to_owned(), language).await?.id;
+    let result = await_command_result(cluster_id, context_id, command_id, None).await?;
+    let results = result.results.context("Command finished without results")?;
+
+    match results.result_type.as_str() {
+        "error" => {
+            let summary = results.summary.context("Error result is missing summary")?;
+            anyhow::bail!("{}", results.cause.unwrap_or(summary))
+        },
+        "text" => {
+            let data = results.data.context("Text result is missing data")?;
+            let text = data.as_str().map(str::to_string).unwrap_or(data.to_string());
+            Ok(CellOutput::Text(text))
+        },
+        "table" => {
+            let data = results.data.context("Table result is missing data")?;
+            let schema = results.schema.context("Missing schema for tabular data")?;
+            Ok(CellOutput::Table(format_table_result(&schema, &data)?))
+        },
+        _ => anyhow::bail!("Unexpected result type {}", results.result_type)
+    }
+}
+
+/// Remaining inactivity time below which running on the cluster prints a warning
+const AUTOTERMINATION_WARNING_MINUTES: u64 = 5;
+
+fn warn_about_autotermination(cluster_id: &str, info: &GetClusterInfoResponse) {
+    let (Some(minutes), Some(last_activity_time)) = (info.autotermination_minutes, info.last_activity_time) else {
+        return;
+    };
+    if minutes == 0 {
+        return;
+    }
+
+    let terminates_at = last_activity_time / 1000 + minutes * 60;
+    let remaining_minutes = terminates_at.saturating_sub(crate::config::unix_timestamp()) / 60;
+    if remaining_minutes < AUTOTERMINATION_WARNING_MINUTES {
+        eprintln!(
+            "Warning: cluster `{}` will auto-terminate in about {} minutes if it stays idle (after {} minutes of inactivity)",
+            cluster_id, remaining_minutes, minutes
+        );
+    }
+}
 
 pub async fn check_cluster_state(cluster_id: String, start: bool) -> Result<()> {
-    let state = crate::client::cluster::get_info(cluster_id.to_owned()).await?.state;
+    let info = crate::client::cluster::get_info(cluster_id.to_owned()).await?;
+    let state = info.state.to_owned();
+
+    // Other missing permissions are reported when starting the cluster or creating the context fails
+    if crate::commands::permissions::cluster_access(&info).await == Some(ClusterAccess::None) {
+        anyhow::bail!(
+            "You can't attach to cluster `{}`, it is a single user cluster of `{}`. Ask its owner to assign it to you or select another cluster",
+            info.cluster_name.as_deref().unwrap_or(&cluster_id), info.single_user_name.as_deref().unwrap_or_default()
+        );
+    }
 
     if state == "RUNNING" || state == "RESIZING" {
+        warn_about_autotermination(&cluster_id, &info);
         Ok(())
     } else if state == "TERMINATED" {
         if start {
-           crate::commands::cluster::start().await
+           crate::commands::cluster::start(None).await
         } else {
-            anyhow::bail!("Cluster is terminated. Use the --start flag if you want to start it");
+            let reason = info.termination_reason.as_ref()
+                .map(|reason| format!(" ({})", crate::commands::cluster::describe_termination(reason)))
+                .unwrap_or_default();
+            anyhow::bail!("Cluster is terminated{}. Use the --start flag if you want to start it", reason);
         }
     } else {
-        anyhow::bail!("Can not run command, cluster state is `{}`", state);
+        // The termination reason is kept after the cluster is started again, it only describes terminated clusters
+        let reason = info.state_message.to_owned()
+            .filter(|message| !message.is_empty())
+            .map(|reason| format!(": {}", reason))
+            .unwrap_or_default();
+        anyhow::bail!("Can not run command, cluster state is `{}`{}. See `brichka cluster events` for details", state, reason);
     }
 }
 
-pub async fn run(command: String, language: String, init: bool, start: bool) -> Result<()> {
-    let cluster_id = ClusterConfig::read_local().await.or(ClusterConfig::read_global().await)?.id;
-    check_cluster_state(cluster_id.to_owned(), start).await?;
-    let context_id = get_or_create_context(cluster_id.to_owned(), init).await?;
+fn resolve_path(file: &str) -> PathBuf {
+    let path = Path::new(file);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        Path::new(&crate::CONTEXT.get().unwrap().cwd).join(path)
+    }
+}
 
-    let filtered_command = filter_excluded_sections(&command);
+/// Reads the cell and detects its language from `--language`, a magic command or the file extension, in this order.
+/// The magic command is removed from the cell even if `--language` is set
+async fn read_cell(command: Option<String>, file: Option<String>, language: Option<Language>) -> Result<(String, Option<Language>, Option<PathBuf>)> {
+    let (source, file_language, path) = match file {
+        Some(file) => {
+            let path = resolve_path(&file);
+            let source = fs::read_to_string(&path).await.with_context(|| format!("Failed to read `{}`", path.display()))?;
+            (source, Language::from_extension(&path), Some(path))
+        },
+        None => (command.context("Either a command or --file must be provided")?, None, None)
+    };
 
-    let command_id = crate::client::command::run(filtered_command, cluster_id.to_owned(), context_id.to_owned(), language).await?.id;
+    if let Some((magic_language, cell)) = Language::split_magic(&source) {
+        return Ok((cell, language.or(Some(magic_language)), path));
+    }
+
+    Ok((source, language.or(file_language), path))
+}
+
+/// Default language of the shared execution context the cell will run in
+async fn context_language(name: Option<String>) -> Option<Language> {
+    let name = name.unwrap_or(ContextConfig::DEFAULT_NAME.to_string());
+    ContextConfig::read_local(&name).await.ok().and_then(|context| context.language)
+}
+
+/// Code of a cell and whether its directives and template variables still have to be applied
+pub enum CellSource {
+    /// Code as written, `path` is the file it was read from
+    Raw { code: String, path: Option<PathBuf> },
+    /// Code recorded in the run history, `file` is the file it was originally read from
+    Prepared { code: String, file: Option<String> },
+}
+
+impl CellSource {
+    fn file(&self) -> Option<String> {
+        match self {
+            CellSource::Raw { path, .. } => path.as_ref().map(|path| path.to_string_lossy().to_string()),
+            CellSource::Prepared { file, .. } => file.to_owned(),
+        }
+    }
+}
 
-    let raw_result = await_command_result(cluster_id, context_id, command_id).await?;
+/// Applies directives and template variables to the cell, prepared cells are returned as they are
+async fn prepare_code(source: &CellSource, language: Language, sent_files: Option<&[String]>, vars: Vec<(String, String)>) -> Result<Cell> {
+    let (code, path) = match source {
+        CellSource::Raw { code, path } => (code, path),
+        CellSource::Prepared { code, .. } => return Ok(Cell { code: code.to_owned(), using_files: Vec::new(), variables: Vec::new() }),
+    };
+    let mut cell = crate::directives::preprocess(code, language, path.as_deref(), sent_files)?;
+    let vars = crate::variables::resolve(std::mem::take(&mut cell.variables), VarsConfig::read_local().await?.vars, vars)?;
+    cell.code = crate::variables::substitute(&cell.code, language, &vars);
+    Ok(cell)
+}
+
+/// Files already sent to the execution context, `None` if `//> using file` directives are ignored
+fn sent_files(context: Option<&ContextConfig>, using_files: bool) -> Option<Vec<String>> {
+    using_files.then(|| context.map(|config| config.sent_files.to_owned()).unwrap_or_default())
+}
+
+async fn run_on_cluster(source: &CellSource, language: Language, args: RunArgs) -> Result<Execution> {
+    let cluster_id = ClusterConfig::read_current().await?.id;
+
+    // Invalid directives and variables fail before a cluster is started or a context is created
+    let context_name = args.context.to_owned().unwrap_or(ContextConfig::DEFAULT_NAME.to_string());
+    let expected_sent_files = sent_files(ContextConfig::read_local(&context_name).await.ok().as_ref(), args.using_files);
+    let cell = prepare_code(source, language, expected_sent_files.as_deref(), args.vars.to_owned()).await?;
+
+    check_cluster_state(cluster_id.to_owned(), args.start).await?;
+    let context = get_or_create_context(cluster_id.to_owned(), args.context.to_owned(), args.init, args.replay, language).await?;
+
+    // A recreated or temporary context has none of the previously sent files
+    let context_sent_files = match &context {
+        ExecutionContext::Shared(config) => sent_files(Some(config), args.using_files),
+        ExecutionContext::Temporary(_) => sent_files(None, args.using_files),
+    };
+    let cell = if context_sent_files != expected_sent_files {
+        prepare_code(source, language, context_sent_files.as_deref(), args.vars).await?
+    } else {
+        cell
+    };
+
+    let result = run_in_context(cell, language, cluster_id.to_owned(), &context).await;
+
+    if let ExecutionContext::Temporary(context_id) = context
+        && let Err(e) = crate::commands::destroy_context(cluster_id, context_id.to_owned()).await
+    {
+        eprintln!("Warning: failed to destroy the temporary execution context `{}`, it can be removed later with `brichka context gc`: {}", context_id, e);
+    }
+
+    result
+}
+
+async fn run_in_context(cell: Cell, language: Language, cluster_id: String, context: &ExecutionContext) -> Result<Execution> {
+    let context_id = context.id();
+
+    let command_id = crate::client::command::run(cell.code.to_owned(), cluster_id.to_owned(), context_id.to_owned(), language).await?.id;
+
+    let progress = crate::progress::is_enabled().then(|| SparkProgress::new(cluster_id.to_owned(), command_id.to_owned()));
+    let raw_result = await_command_result(cluster_id.to_owned(), context_id, command_id.to_owned(), progress).await?;
     let formatted_result = format_command_result(raw_result).await?;
-    let formatted_result_str = serde_json::to_string(&formatted_result)?;
+
+    if let ExecutionContext::Shared(config) = context
+        && !matches!(formatted_result, CommandRunResult::Error { .. })
+    {
+        ContextHistory::append_local(&config.name, &ExecutedCell::new(cell.code.to_owned(), language)).await?;
+    }
+
+    if let ExecutionContext::Shared(config) = context
+        && !cell.using_files.is_empty()
+        && !matches!(formatted_result, CommandRunResult::Error { .. })
+    {
+        let mut config = config.clone();
+        config.sent_files.extend(cell.using_files);
+        config.write_local().await?;
+    }
+
+    let context = match context {
+        ExecutionContext::Shared(config) => Some(config.name.to_owned()),
+        ExecutionContext::Temporary(_) => None,
+    };
+    Ok(Execution { code: cell.code, cluster_id: Some(cluster_id), warehouse_id: None, context, command_id, result: formatted_result })
+}
+
+fn is_statement_finished(response: &StatementResponse) -> bool {
+    matches!(response.status.state.as_str(), "SUCCEEDED" | "FAILED" | "CANCELED" | "CLOSED")
+}
+
+async fn await_statement_result(statement_id: String) -> Result<StatementResponse> {
+    loop {
+        let response = crate::client::statement::get(statement_id.to_owned()).await?;
+        if is_statement_finished(&response) {
+            return Ok(response);
+        }
+        sleep(Duration::from_secs(2)).await;
+    }
+}
+
+fn convert_statement_value(type_name: &str, value: Option<String>) -> serde_json::Value {
+    let Some(value) = value else {
+        return serde_json::Value::Null;
+    };
+
+    let converted = match type_name {
+        "BYTE" | "SHORT" | "INT" | "LONG" => value.parse::<i64>().ok().map(serde_json::Value::from),
+        "FLOAT" | "DOUBLE" => value.parse::<f64>().ok().map(serde_json::Value::from),
+        "BOOLEAN" => value.parse::<bool>().ok().map(serde_json::Value::from),
+        "ARRAY" | "STRUCT" | "MAP" => serde_json::from_str(&value).ok(),
+        _ => None,
+    };
+
+    converted.unwrap_or(serde_json::Value::String(value))
+}
+
+fn format_statement_rows(columns: &[Column], data: Vec<Vec<Option<String>>>) -> Result<Vec<serde_json::Value>> {
+    data.into_iter()
+        .map(|row| {
+            if row.len() != columns.len() {
+                anyhow::bail!("Row length {} doesn't match schema length {}", row.len(), columns.len());
+            }
+            let json_obj = columns.iter()
+                .zip(row)
+                .map(|(column, value)| (column.name.to_owned(), convert_statement_value(&column.type_name, value)))
+                .collect::<serde_json::Map<String, serde_json::Value>>();
+            Ok(serde_json::Value::Object(json_obj))
+        })
+        .collect()
+}
+
+/// Downloads a result chunk. The links of the first chunk are part of the statement response, the others are requested
+async fn download_statement_chunk(statement_id: String, chunk_index: usize, links: Option<Vec<ExternalLink>>, columns: Arc<Vec<Column>>) -> Result<DownloadedChunk> {
+    let links = match links {
+        Some(links) => links,
+        None => crate::client::statement::get_result_chunk(statement_id, chunk_index).await?
+            .external_links.context("Result chunk is missing external links")?,
+    };
+
+    let mut rows = Vec::new();
+    let mut bytes = 0;
+    for link in links {
+        let data = crate::client::statement::download_external_link(&link.external_link).await?;
+        bytes += data.len() as u64;
+        let data_array = serde_json::from_slice::<Vec<Vec<Option<String>>>>(&data).context("Failed to parse result chunk")?;
+        rows.extend(format_statement_rows(&columns, data_array)?);
+    }
+
+    Ok((chunk_index, rows, bytes))
+}
+
+/// Result of downloading a chunk: its index, rows and size in bytes
+type DownloadedChunk = (usize, Vec<serde_json::Value>, u64);
+
+/// Downloads external link chunks concurrently with `download` and writes them to the result file in order.
+/// At most `parallelism` chunks are downloaded or waiting to be written at the same time
+async fn write_external_links<F, Fut>(writer: &mut TableResultWriter, first_links: Vec<ExternalLink>, total_chunks: usize, parallelism: usize, download: F) -> Result<()>
+where
+    F: Fn(usize, Option<Vec<ExternalLink>>) -> Fut,
+    Fut: Future<Output = Result<DownloadedChunk>> + Send + 'static,
+{
+    let parallelism = parallelism.max(1);
+
+    let mut downloads = JoinSet::new();
+    let mut pending = BTreeMap::new();
+    let mut next_download = 0;
+    let mut next_write = 0;
+    let mut downloaded_bytes = 0;
+    let mut first_links = Some(first_links);
+
+    while next_write < total_chunks {
+        while next_download < total_chunks && next_download < next_write + parallelism {
+            let links = if next_download == 0 { first_links.take() } else { None };
+            downloads.spawn(download(next_download, links));
+            next_download += 1;
+        }
+
+        let (chunk_index, rows, bytes) = downloads.join_next().await.context("Result chunk download was lost")???;
+        downloaded_bytes += bytes;
+        pending.insert(chunk_index, rows);
+
+        while let Some(rows) = pending.remove(&next_write) {
+            writer.write_rows(&rows).await?;
+            next_write += 1;
+            crate::progress::report(ProgressEvent::Download {
+                chunk: next_write,
+                total_chunks,
+                rows: writer.rows_written,
+                bytes: downloaded_bytes,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+async fn format_statement_result(response: StatementResponse, parallelism: usize) -> Result<CommandRunResult> {
+    match response.status.state.as_str() {
+        "SUCCEEDED" => {
+            let manifest = response.manifest.context("Statement result is missing manifest")?;
+            let columns = manifest.schema.columns;
+            let mut writer = TableResultWriter::create(&response.statement_id).await?;
+
+            let first_links = response.result.as_ref().and_then(|result| result.external_links.to_owned());
+            if let Some(first_links) = first_links {
+                let total_chunks = manifest.total_chunk_count.unwrap_or(1);
+                let statement_id = response.statement_id.to_owned();
+                let columns = Arc::new(columns);
+                write_external_links(&mut writer, first_links, total_chunks, parallelism, |chunk_index, links| {
+                    download_statement_chunk(statement_id.to_owned(), chunk_index, links, columns.clone())
+                }).await?;
+            } else {
+                let mut next_chunk = response.result;
+                while let Some(chunk) = next_chunk {
+                    writer.write_rows(&format_statement_rows(&columns, chunk.data_array.unwrap_or_default())?).await?;
+                    next_chunk = match chunk.next_chunk_index {
+                        Some(index) => Some(crate::client::statement::get_result_chunk(response.statement_id.to_owned(), index).await?),
+                        None => None,
+                    };
+                }
+            }
+
+            let path = writer.finish().await?;
+            Ok(CommandRunResult::Table { path })
+        },
+        "FAILED" => {
+            let error = response.status.error.context("Failed statement is missing error")?;
+            let message = error.message.unwrap_or_else(|| "Statement failed".to_string());
+            Ok(CommandRunResult::Error { message, cause: error.error_code })
+        },
+        state => Ok(CommandRunResult::Error { message: format!("Statement was {}", state.to_lowercase()), cause: None }),
+    }
+}
+
+async fn run_on_warehouse(source: &CellSource, language: Language, args: RunArgs) -> Result<Execution> {
+    if language != Language::Sql {
+        anyhow::bail!("SQL warehouses can only run SQL, got `{}`", language);
+    }
+
+    let warehouse_id = WarehouseConfig::read_local().await.or(WarehouseConfig::read_global().await)
+        .context("No SQL warehouse is configured. Select one with `brichka config warehouse`")?.id;
+    let cell = prepare_code(source, language, None, args.vars).await?;
+
+    let disposition = if args.external_links { Disposition::ExternalLinks } else { Disposition::Inline };
+    let response = crate::client::statement::execute(cell.code.to_owned(), warehouse_id.to_owned(), disposition).await?;
+    let statement_id = response.statement_id.to_owned();
+
+    let response = if is_statement_finished(&response) {
+        response
+    } else {
+        tokio::select! {
+            result = await_statement_result(statement_id.to_owned()) => result?,
+            _ = tokio::signal::ctrl_c() => {
+                crate::client::statement::cancel(statement_id).await?;
+                anyhow::bail!("Statement was cancelled");
+            }
+        }
+    };
+
+    let result = format_statement_result(response, args.parallel_downloads).await?;
+    Ok(Execution { code: cell.code, cluster_id: None, warehouse_id: Some(warehouse_id), context: None, command_id: statement_id, result })
+}
+
+pub async fn run(args: RunArgs) -> Result<()> {
+    let (command, language, path) = read_cell(args.command.to_owned().map(MaybeStdin::into_inner), args.file.to_owned(), args.language).await?;
+    let language = match language {
+        Some(language) => language,
+        None if args.warehouse => Language::Sql,
+        None => context_language(args.context.to_owned()).await
+            .context("Can not infer the language of the command. Set it with --language or initialize the execution context with `brichka init --language`")?,
+    };
+
+    run_source(CellSource::Raw { code: command, path }, language, args).await
+}
+
+/// Runs the cell on the configured target, records it in the run history and prints the result
+pub async fn run_source(source: CellSource, language: Language, args: RunArgs) -> Result<()> {
+    let started = Instant::now();
+    let execution = if args.warehouse {
+        run_on_warehouse(&source, language, args).await?
+    } else {
+        run_on_cluster(&source, language, args).await?
+    };
+
+    let record = RunRecord {
+        id: 0,
+        timestamp: crate::config::unix_timestamp(),
+        source_hash: format!("{:x}", Sha256::digest(execution.code.as_bytes())),
+        source: execution.code,
+        language,
+        file: source.file(),
+        cluster_id: execution.cluster_id,
+        warehouse_id: execution.warehouse_id,
+        context: execution.context,
+        command_id: execution.command_id,
+        duration_ms: started.elapsed().as_millis().try_into().unwrap_or(u64::MAX),
+        result_type: execution.result.kind().to_string(),
+        result_path: execution.result.path(),
+    };
+    if let Err(e) = RunHistory::append_local(record).await {
+        eprintln!("Warning: failed to record the run in history: {}", e);
+    }
+
+    let formatted_result_str = serde_json::to_string(&execution.result)?;
     println!("{}", formatted_result_str);
 
     Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn column(name: &str, type_name: &str) -> Column {
+        Column { name: name.to_string(), type_name: type_name.to_string() }
+    }
+
+    #[test]
+    fn converts_statement_values_by_type() {
+        let value = |value: &str| Some(value.to_string());
+        assert_eq!(convert_statement_value("INT", value("42")), serde_json::json!(42));
+        assert_eq!(convert_statement_value("DOUBLE", value("1.5")), serde_json::json!(1.5));
+        assert_eq!(convert_statement_value("BOOLEAN", value("true")), serde_json::json!(true));
+        assert_eq!(convert_statement_value("STRUCT", value(r#"{"a":1}"#)), serde_json::json!({"a": 1}));
+        assert_eq!(convert_statement_value("ARRAY", value("[1,2]")), serde_json::json!([1, 2]));
+        assert_eq!(convert_statement_value("STRING", value("00123")), serde_json::json!("00123"));
+        assert_eq!(convert_statement_value("INT", None), serde_json::Value::Null);
+        assert_eq!(convert_statement_value("INT", value("12x")), serde_json::json!("12x"));
+        assert_eq!(convert_statement_value("STRUCT", value("{broken")), serde_json::json!("{broken"));
+    }
+
+    #[test]
+    fn formats_statement_rows_by_column() {
+        let columns = vec![column("id", "LONG"), column("name", "STRING")];
+        let rows = format_statement_rows(&columns, vec![vec![Some("1".to_string()), None]]).unwrap();
+        assert_eq!(rows, vec![serde_json::json!({"id": 1, "name": null})]);
+        assert!(format_statement_rows(&columns, vec![vec![Some("1".to_string())]]).is_err());
+    }
 
+    #[tokio::test]
+    async fn writes_chunks_in_order_when_downloads_finish_out_of_order() {
+        let total_chunks = 6;
+        let parallelism = 3;
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let first_links = vec![ExternalLink { external_link: "https://example.com/0".to_string() }];
+
+        let mut writer = TableResultWriter::create(&format!("test-chunks-{}", std::process::id())).await.unwrap();
+        write_external_links(&mut writer, first_links, total_chunks, parallelism, |chunk_index, links| {
+            let running = running.clone();
+            let max_running = max_running.clone();
+            async move {
+                assert_eq!(links.is_some(), chunk_index == 0);
+                max_running.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                // Later chunks of a window finish first
+                sleep(Duration::from_millis(10 * (parallelism - chunk_index % parallelism) as u64)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok((chunk_index, vec![serde_json::json!({ "chunk": chunk_index })], 1))
+            }
+        }).await.unwrap();
+        let path = writer.finish().await.unwrap();
+
+        let rows = serde_json::from_str::<Vec<serde_json::Value>>(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(rows, (0..total_chunks).map(|chunk| serde_json::json!({ "chunk": chunk })).collect::<Vec<serde_json::Value>>());
+        assert!((2..=parallelism).contains(&max_running.load(Ordering::SeqCst)));
+        std::fs::remove_file(path).unwrap();
+    }
 }
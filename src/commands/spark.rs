@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use tokio::time::{Duration, Instant};
+
+use crate::{client::spark::Job, progress::ProgressEvent};
+
+/// Status of a Spark job that has not finished yet
+const RUNNING: &str = "RUNNING";
+/// The Spark UI is polled less often than the command, every request may create a temporary token
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Reports the Spark jobs of a command running on the cluster. Progress is best effort,
+/// once the Spark UI can't be queried, e.g. on clusters without driver proxy access, it is not queried again
+pub struct SparkProgress {
+    cluster_id: String,
+    command_id: String,
+    started: Instant,
+    last_poll: Option<Instant>,
+    application_id: Option<String>,
+    /// Status and finished tasks of every job at the time it was last reported
+    reported: HashMap<u64, (String, u64)>,
+    ui_reported: bool,
+    disabled: bool,
+}
+
+impl SparkProgress {
+    pub fn new(cluster_id: String, command_id: String) -> SparkProgress {
+        SparkProgress {
+            cluster_id,
+            command_id,
+            started: Instant::now(),
+            last_poll: None,
+            application_id: None,
+            reported: HashMap::new(),
+            ui_reported: false,
+            disabled: false,
+        }
+    }
+
+    async fn application_id(&mut self) -> Option<String> {
+        if self.application_id.is_none() {
+            let applications = crate::client::spark::list_applications(&self.cluster_id).await.ok()?;
+            self.application_id = applications.into_iter().next().map(|application| application.id);
+        }
+        self.application_id.to_owned()
+    }
+
+    /// Jobs of the command, Databricks puts the command id in the job group
+    fn command_jobs(&self, jobs: Vec<Job>) -> Vec<Job> {
+        jobs.into_iter()
+            .filter(|job| job.job_group.as_ref().is_some_and(|group| group.contains(&self.command_id)))
+            .collect()
+    }
+
+    /// Reports the job if its status or finished tasks changed since it was last reported
+    fn report(&mut self, job: &Job) {
+        let completed_tasks = job.num_completed_tasks + job.num_skipped_tasks;
+        let current = (job.status.to_owned(), completed_tasks);
+        if self.reported.get(&job.job_id) == Some(&current) {
+            return;
+        }
+        crate::progress::report(ProgressEvent::SparkJob {
+            job_id: job.job_id,
+            name: &job.name,
+            status: &job.status,
+            completed_tasks,
+            total_tasks: job.num_tasks,
+            active_stages: job.num_active_stages,
+            elapsed_secs: self.started.elapsed().as_secs(),
+        });
+        self.reported.insert(job.job_id, current);
+    }
+
+    /// Reports the final status of reported jobs that stopped running since the previous poll
+    async fn report_finished(&mut self, application_id: &str, running: &[u64]) {
+        let mut finished = self.reported.iter()
+            .filter(|(job_id, (status, _))| status == RUNNING && !running.contains(job_id))
+            .map(|(job_id, _)| *job_id)
+            .collect::<Vec<u64>>();
+        finished.sort();
+        for job_id in finished {
+            if let Ok(job) = crate::client::spark::get_job(&self.cluster_id, application_id, job_id).await {
+                self.report(&job);
+            }
+        }
+    }
+
+    /// Reports the Spark UI link on the first call and the jobs of the command whose progress changed since the previous one.
+    /// Calls within `POLL_INTERVAL` of the previous poll do nothing
+    pub async fn poll(&mut self) {
+        if self.disabled || self.last_poll.is_some_and(|last_poll| last_poll.elapsed() < POLL_INTERVAL) {
+            return;
+        }
+        self.last_poll = Some(Instant::now());
+
+        if !self.ui_reported {
+            self.ui_reported = true;
+            if let Ok(url) = crate::commands::cluster::spark_ui_url(&self.cluster_id).await {
+                crate::progress::report(ProgressEvent::SparkUi { cluster_id: &self.cluster_id, command_id: &self.command_id, url });
+            }
+        }
+
+        let Some(application_id) = self.application_id().await else {
+            self.disabled = true;
+            return;
+        };
+        let Ok(jobs) = crate::client::spark::list_running_jobs(&self.cluster_id, &application_id).await else {
+            self.disabled = true;
+            return;
+        };
+
+        let jobs = self.command_jobs(jobs);
+        let running = jobs.iter().map(|job| job.job_id).collect::<Vec<u64>>();
+        self.report_finished(&application_id, &running).await;
+        for job in &jobs {
+            self.report(job);
+        }
+    }
+
+    /// Reports the final status of the jobs still running at the previous poll, once the command is finished
+    pub async fn finish(&mut self) {
+        if self.disabled {
+            return;
+        }
+        if let Some(application_id) = self.application_id.to_owned() {
+            self.report_finished(&application_id, &[]).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(job_id: u64, job_group: Option<&str>) -> Job {
+        serde_json::from_value(serde_json::json!({
+            "jobId": job_id,
+            "name": "collect at <console>:3",
+            "status": "RUNNING",
+            "jobGroup": job_group,
+            "numTasks": 10,
+            "numCompletedTasks": 2,
+            "numActiveStages": 1,
+        })).unwrap()
+    }
+
+    #[test]
+    fn keeps_only_jobs_of_the_command() {
+        let progress = SparkProgress::new("0101-abc".to_string(), "5d9a".to_string());
+        let jobs = vec![job(1, Some("1234_5d9a_job")), job(2, Some("1234_7e1b_job")), job(3, None)];
+        let ids = progress.command_jobs(jobs).iter().map(|job| job.job_id).collect::<Vec<u64>>();
+        assert_eq!(ids, vec![1]);
+        assert!(progress.command_jobs(vec![job(2, Some("1234_7e1b_job"))]).is_empty());
+    }
+}
@@ -2,16 +2,19 @@ use crate::config::{ClusterConfig, ContextConfig};
 
 use anyhow::Result;
 
-pub async fn context() -> Result<()> {
-    let cluster = ClusterConfig::read_local().await.or(ClusterConfig::read_global().await)?;
-    let context = ContextConfig::read_local().await?;
-    let status = crate::client::context::get_status(cluster.id, context.id).await?.status;
+pub async fn context(name: String) -> Result<()> {
+    let context = ContextConfig::read_local(&name).await?;
+    let cluster_id = match context.cluster_id {
+        Some(cluster_id) => cluster_id,
+        None => ClusterConfig::read_current().await?.id,
+    };
+    let status = crate::client::context::get_status(cluster_id, context.id).await?.status;
     println!("{}", status);
     Ok(())
 }
 
 pub async fn cluster() -> Result<()> {
-    let cluster = ClusterConfig::read_local().await.or(ClusterConfig::read_global().await)?;
+    let cluster = ClusterConfig::read_current().await?;
     let state = crate::client::cluster::get_info(cluster.id).await?.state;
     println!("{}", state);
     Ok(())
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use tokio::time::{ sleep, Duration, Instant };
+
+use crate::{config::WarehouseConfig, progress::ProgressEvent};
+
+
+async fn read_warehouse_config() -> Result<WarehouseConfig> {
+    WarehouseConfig::read_local().await.or(WarehouseConfig::read_global().await)
+}
+
+pub async fn list() -> Result<()> {
+    let response = crate::client::warehouse::list().await?;
+    let result_json = serde_json::to_string(&response.warehouses)?;
+    println!("{}", result_json);
+    Ok(())
+}
+
+pub async fn status() -> Result<()> {
+    let warehouse = read_warehouse_config().await?;
+    let state = crate::client::warehouse::get_info(warehouse.id).await?.state;
+    println!("{}", state);
+    Ok(())
+}
+
+/// Polls the warehouse until it reaches `target_state`, reporting state transitions as progress
+async fn await_state(warehouse_id: String, target_state: &str) -> Result<()> {
+    let started = Instant::now();
+    let mut last_state = String::new();
+    loop {
+        let state = crate::client::warehouse::get_info(warehouse_id.to_owned()).await?.state;
+        if state != last_state {
+            crate::progress::report(ProgressEvent::State {
+                resource: "warehouse",
+                id: &warehouse_id,
+                state: &state,
+                elapsed_secs: started.elapsed().as_secs(),
+            });
+            last_state = state.to_owned();
+        }
+
+        if state == target_state {
+            break;
+        } else if state == "DELETING" || state == "DELETED" {
+            anyhow::bail!("Warehouse is `{}`", state);
+        }
+        sleep(Duration::from_secs(2)).await;
+    };
+    Ok(())
+}
+
+pub async fn start(wait: bool) -> Result<()> {
+    let warehouse_id = read_warehouse_config().await?.id;
+
+    let state = crate::client::warehouse::get_info(warehouse_id.to_owned()).await?.state;
+    match state.as_str() {
+        "RUNNING" => return Ok(()),
+        "STOPPED" | "STOPPING" => {
+            crate::client::warehouse::start(warehouse_id.to_owned()).await?;
+        }
+        _ => {}
+    }
+
+    if wait {
+        await_state(warehouse_id, "RUNNING").await?;
+    }
+
+    Ok(())
+}
+
+pub async fn stop(wait: bool) -> Result<()> {
+    let warehouse_id = read_warehouse_config().await?.id;
+
+    let state = crate::client::warehouse::get_info(warehouse_id.to_owned()).await?.state;
+    if state == "STOPPED" {
+        return Ok(());
+    }
+    crate::client::warehouse::stop(warehouse_id.to_owned()).await?;
+
+    if wait {
+        await_state(warehouse_id, "STOPPED").await?;
+    }
+
+    Ok(())
+}
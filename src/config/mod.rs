@@ -1,19 +1,25 @@
-use tokio::fs;
-use std::env;
+use tokio::{fs, io::AsyncWriteExt};
+use std::{collections::HashMap, env};
 
 use serde::{Deserialize, Serialize};
 
 use anyhow::{Context, Result};
 
+use crate::language::Language;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClusterConfig {
     pub id: String,
-    pub name: String
+    pub name: String,
+    /// Created with `brichka cluster create`, so it can be destroyed with `brichka cluster destroy`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ephemeral: bool,
 }
 
 impl ClusterConfig {
     
     const CONFIG_FILE: &str = "brichka/cluster.json";
+    pub const ENV_VAR: &str = "BRICHKA_CLUSTER";
 
     fn local_path() -> String {
         let cwd = crate::CONTEXT.get().unwrap().cwd.to_owned();
@@ -28,7 +34,8 @@ impl ClusterConfig {
     pub fn new(cluster: &crate::client::cluster::Cluster) -> ClusterConfig {
         ClusterConfig {
             id: cluster.id.to_owned(),
-            name: cluster.name.to_owned()
+            name: cluster.name.to_owned(),
+            ephemeral: false,
         }
     }
 
@@ -45,6 +52,16 @@ impl ClusterConfig {
         Self::read(Self::global_path()?).await
     }
 
+    /// Cluster id in `BRICHKA_CLUSTER` takes precedence over the local and then the global config
+    pub async fn read_current() -> Result<ClusterConfig> {
+        if let Ok(id) = env::var(Self::ENV_VAR)
+            && !id.is_empty()
+        {
+            return Ok(ClusterConfig { id: id.to_owned(), name: id, ephemeral: false });
+        }
+        Self::read_local().await.or(Self::read_global().await)
+    }
+
 
     async fn write(&self, path: String) -> Result<()> {
         let raw_json = serde_json::to_string(self)?;
@@ -62,38 +79,55 @@ impl ClusterConfig {
     pub async fn write_global(&self) -> Result<()> {
         self.write(Self::global_path()?).await
     }
+
+    pub async fn remove_local() -> Result<()> {
+        let path = Self::local_path();
+        if fs::try_exists(&path).await? {
+            fs::remove_file(path).await?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ContextConfig {
+pub struct WarehouseConfig {
     pub id: String,
+    pub name: String
 }
 
-impl ContextConfig {
-    
-    const CONFIG_FILE: &str = "brichka/context.json";
+impl WarehouseConfig {
+
+    const CONFIG_FILE: &str = "brichka/warehouse.json";
 
     fn local_path() -> String {
         let cwd = crate::CONTEXT.get().unwrap().cwd.to_owned();
         format!("{}/.{}", cwd, Self::CONFIG_FILE)
     }
 
-    pub fn new(id: String) -> ContextConfig {
-        ContextConfig {
-            id: id
+    fn global_path() -> Result<String> {
+        let home_dir = env::home_dir().context("Failed to locate the home directory")?;
+        Ok(format!("{}/.config/{}", home_dir.to_string_lossy(), Self::CONFIG_FILE))
+    }
+
+    pub fn new(warehouse: &crate::client::warehouse::Warehouse) -> WarehouseConfig {
+        WarehouseConfig {
+            id: warehouse.id.to_owned(),
+            name: warehouse.name.to_owned()
         }
     }
 
-    async fn read(path: String) -> Result<ContextConfig> {
-        
+    async fn read(path: String) -> Result<WarehouseConfig> {
         let raw_json = fs::read_to_string(path).await?;
         serde_json::from_str(&raw_json).context("Failed to deserialize config")
     }
 
-    pub async fn read_local() -> Result<ContextConfig> {
+    pub async fn read_local() -> Result<WarehouseConfig> {
         Self::read(Self::local_path()).await
     }
 
+    pub async fn read_global() -> Result<WarehouseConfig> {
+        Self::read(Self::global_path()?).await
+    }
 
     async fn write(&self, path: String) -> Result<()> {
         let raw_json = serde_json::to_string(self)?;
@@ -108,6 +142,445 @@ impl ContextConfig {
         self.write(Self::local_path()).await
     }
 
+    pub async fn write_global(&self) -> Result<()> {
+        self.write(Self::global_path()?).await
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextConfig {
+    /// Name of the context, derived from the config file name
+    #[serde(skip)]
+    pub name: String,
+    pub id: String,
+    /// Cluster the context was created on. Not set for contexts created by older versions
+    #[serde(default)]
+    pub cluster_id: Option<String>,
+    /// Language the context was created in, used for cells without an explicit language
+    #[serde(default)]
+    pub language: Option<Language>,
+    /// Files that were inlined because of a `//> using file` directive and are already defined in the context
+    #[serde(default)]
+    pub sent_files: Vec<String>,
+}
+
+impl ContextConfig {
+
+    pub const DEFAULT_NAME: &str = "default";
+    const CONFIG_DIR: &str = "brichka/contexts";
+    /// Location of the single context used before named contexts were introduced
+    const LEGACY_CONFIG_FILE: &str = "brichka/context.json";
+
+    fn local_dir() -> String {
+        let cwd = crate::CONTEXT.get().unwrap().cwd.to_owned();
+        format!("{}/.{}", cwd, Self::CONFIG_DIR)
+    }
+
+    fn local_path(name: &str) -> String {
+        format!("{}/{}.json", Self::local_dir(), name)
+    }
+
+    fn legacy_path() -> String {
+        let cwd = crate::CONTEXT.get().unwrap().cwd.to_owned();
+        format!("{}/.{}", cwd, Self::LEGACY_CONFIG_FILE)
+    }
+
+    pub fn validate_name(name: &str) -> Result<()> {
+        let is_valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !is_valid {
+            anyhow::bail!("Invalid context name `{}`. Use letters, digits, `-` and `_`", name);
+        }
+        Ok(())
+    }
+
+    pub fn new(name: String, id: String, cluster_id: String, language: Language) -> ContextConfig {
+        ContextConfig {
+            name,
+            id,
+            cluster_id: Some(cluster_id),
+            language: Some(language),
+            sent_files: Vec::new()
+        }
+    }
+
+    async fn read(path: String, name: &str) -> Result<ContextConfig> {
+        let raw_json = fs::read_to_string(path).await?;
+        let mut config: ContextConfig = serde_json::from_str(&raw_json).context("Failed to deserialize config")?;
+        config.name = name.to_string();
+        Ok(config)
+    }
+
+    pub async fn read_local(name: &str) -> Result<ContextConfig> {
+        Self::validate_name(name)?;
+        let path = Self::local_path(name);
+        // A default context that only exists in the legacy location is read from there until it is written again
+        if name == Self::DEFAULT_NAME && !fs::try_exists(&path).await? && fs::try_exists(Self::legacy_path()).await? {
+            return Self::read(Self::legacy_path(), name).await;
+        }
+        Self::read(path, name).await
+    }
+
+    /// All contexts of the current working directory sorted by name
+    pub async fn list_local() -> Result<Vec<ContextConfig>> {
+        let mut names = Vec::new();
+        if let Ok(mut entries) = fs::read_dir(Self::local_dir()).await {
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().is_some_and(|extension| extension == "json")
+                    && let Some(name) = path.file_stem()
+                {
+                    names.push(name.to_string_lossy().to_string());
+                }
+            }
+        }
+        if !names.iter().any(|name| name == Self::DEFAULT_NAME) && fs::try_exists(Self::legacy_path()).await? {
+            names.push(Self::DEFAULT_NAME.to_string());
+        }
+        names.sort();
+
+        let mut contexts = Vec::new();
+        for name in names {
+            contexts.push(Self::read_local(&name).await?);
+        }
+        Ok(contexts)
+    }
+
+    async fn write(&self, path: String) -> Result<()> {
+        let raw_json = serde_json::to_string(self)?;
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, raw_json).await?;
+        Ok(())
+    }
+
+    pub async fn remove_local(&self) -> Result<()> {
+        let path = Self::local_path(&self.name);
+        if fs::try_exists(&path).await? {
+            fs::remove_file(path).await?;
+        }
+        if self.name == Self::DEFAULT_NAME && fs::try_exists(Self::legacy_path()).await? {
+            fs::remove_file(Self::legacy_path()).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn write_local(&self) -> Result<()> {
+        Self::validate_name(&self.name)?;
+        self.write(Self::local_path(&self.name)).await?;
+        // The new location takes over from the legacy file
+        if self.name == Self::DEFAULT_NAME && fs::try_exists(Self::legacy_path()).await? {
+            fs::remove_file(Self::legacy_path()).await?;
+        }
+        Ok(())
+    }
+
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreatedContext {
+    pub cluster_id: String,
+    pub id: String,
+    /// Unix timestamp in seconds, `0` for contexts recorded before it was tracked
+    #[serde(default)]
+    pub created_at: u64,
+    /// Process that created the context
+    #[serde(default)]
+    pub pid: Option<u32>,
+}
+
+impl CreatedContext {
+    pub fn new(cluster_id: String, id: String) -> CreatedContext {
+        CreatedContext { cluster_id, id, created_at: unix_timestamp(), pid: Some(std::process::id()) }
+    }
+}
+
+/// Every execution context brichka created from the current working directory and has not destroyed yet
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CreatedContextsConfig {
+    pub contexts: Vec<CreatedContext>,
+}
+
+impl CreatedContextsConfig {
+
+    const CONFIG_FILE: &str = "brichka/created-contexts.json";
+    const LOCK_STALE_SECS: u64 = 10;
+
+    fn local_path() -> String {
+        let cwd = crate::CONTEXT.get().unwrap().cwd.to_owned();
+        format!("{}/.{}", cwd, Self::CONFIG_FILE)
+    }
+
+    async fn read(path: String) -> Result<CreatedContextsConfig> {
+        let raw_json = fs::read_to_string(path).await?;
+        serde_json::from_str(&raw_json).context("Failed to deserialize config")
+    }
+
+    pub async fn read_local() -> Result<CreatedContextsConfig> {
+        let path = Self::local_path();
+        if !fs::try_exists(&path).await? {
+            return Ok(CreatedContextsConfig::default());
+        }
+        Self::read(path).await
+    }
+
+    async fn write(&self, path: String) -> Result<()> {
+        let raw_json = serde_json::to_string(self)?;
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, raw_json).await?;
+        Ok(())
+    }
+
+    /// Replaces the file at once, so a concurrent read never sees it half written
+    pub async fn write_local(&self) -> Result<()> {
+        let path = Self::local_path();
+        let temp_path = format!("{}.{}.tmp", path, std::process::id());
+        self.write(temp_path.to_owned()).await?;
+        fs::rename(temp_path, path).await?;
+        Ok(())
+    }
+
+    /// Takes the lock of the file, so concurrent runs from the same directory don't lose each other's changes.
+    /// A lock left behind by a crashed process is taken over once it is older than `LOCK_STALE_SECS`
+    async fn lock() -> Result<CreatedContextsLock> {
+        let path = format!("{}.lock", Self::local_path());
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        loop {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path).await {
+                Ok(_) => return Ok(CreatedContextsLock { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let age = fs::metadata(&path).await.ok()
+                        .and_then(|metadata| metadata.modified().ok())
+                        .and_then(|modified| modified.elapsed().ok())
+                        .unwrap_or_default();
+                    if age.as_secs() >= Self::LOCK_STALE_SECS {
+                        let _ = fs::remove_file(&path).await;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                },
+                Err(e) => return Err(e).with_context(|| format!("Failed to lock `{}`", path)),
+            }
+        }
+    }
+
+    async fn update(change: impl FnOnce(&mut Vec<CreatedContext>)) -> Result<()> {
+        let lock = Self::lock().await?;
+        let mut config = Self::read_local().await?;
+        change(&mut config.contexts);
+        let result = config.write_local().await;
+        lock.release().await;
+        result
+    }
+
+    pub async fn add(context: CreatedContext) -> Result<()> {
+        Self::update(|contexts| contexts.push(context)).await
+    }
+
+    pub async fn remove(cluster_id: &str, id: &str) -> Result<()> {
+        Self::update(|contexts| contexts.retain(|c| c.cluster_id != cluster_id || c.id != id)).await
+    }
+}
+
+struct CreatedContextsLock {
+    path: String,
+}
+
+impl CreatedContextsLock {
+    async fn release(self) {
+        let _ = fs::remove_file(self.path).await;
+    }
+}
+
+/// A cell that was successfully executed in a shared execution context
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecutedCell {
+    /// Code as it was sent to the cluster, after directives and variables were applied
+    pub code: String,
+    pub language: Language,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl ExecutedCell {
+    pub fn new(code: String, language: Language) -> ExecutedCell {
+        ExecutedCell { code, language, timestamp: unix_timestamp() }
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Cells executed in each shared execution context, stored as JSONL so they can be replayed into a new context
+pub struct ContextHistory;
+
+impl ContextHistory {
+
+    const CONFIG_DIR: &str = "brichka/history";
+
+    fn local_path(name: &str) -> String {
+        let cwd = crate::CONTEXT.get().unwrap().cwd.to_owned();
+        format!("{}/.{}/{}.jsonl", cwd, Self::CONFIG_DIR, name)
+    }
+
+    pub async fn read_local(name: &str) -> Result<Vec<ExecutedCell>> {
+        let path = Self::local_path(name);
+        if !fs::try_exists(&path).await? {
+            return Ok(Vec::new());
+        }
+        let raw_jsonl = fs::read_to_string(path).await?;
+        raw_jsonl
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).context("Failed to deserialize context history"))
+            .collect()
+    }
+
+    pub async fn append_local(name: &str, cell: &ExecutedCell) -> Result<()> {
+        let path = Self::local_path(name);
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path).await?;
+        let raw_json = serde_json::to_string(cell)?;
+        file.write_all(format!("{}\n", raw_json).as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Replaces the history. The cells are written to a temporary file first, so the previous history is kept if writing fails
+    pub async fn write_local(name: &str, cells: &[ExecutedCell]) -> Result<()> {
+        let path = Self::local_path(name);
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut raw_jsonl = String::new();
+        for cell in cells {
+            raw_jsonl.push_str(&serde_json::to_string(cell)?);
+            raw_jsonl.push('\n');
+        }
+        let temp_path = format!("{}.tmp", path);
+        fs::write(&temp_path, raw_jsonl).await?;
+        fs::rename(temp_path, path).await?;
+        Ok(())
+    }
+
+    pub async fn clear_local(name: &str) -> Result<()> {
+        let path = Self::local_path(name);
+        if fs::try_exists(&path).await? {
+            fs::remove_file(path).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A command executed by `brichka run`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// SHA-256 of the executed code
+    pub source_hash: String,
+    /// Code as it was sent, after directives and variables were applied
+    pub source: String,
+    pub language: Language,
+    pub file: Option<String>,
+    pub cluster_id: Option<String>,
+    pub warehouse_id: Option<String>,
+    /// Name of the shared execution context, if one was used
+    pub context: Option<String>,
+    /// Command id on the cluster or statement id on the SQL warehouse
+    pub command_id: String,
+    pub duration_ms: u64,
+    pub result_type: String,
+    pub result_path: Option<String>,
+}
+
+/// History of every `brichka run` in the project, stored as JSONL
+pub struct RunHistory;
+
+impl RunHistory {
+
+    const CONFIG_FILE: &str = "brichka/runs.jsonl";
+
+    fn local_path() -> String {
+        let cwd = crate::CONTEXT.get().unwrap().cwd.to_owned();
+        format!("{}/.{}", cwd, Self::CONFIG_FILE)
+    }
+
+    pub async fn read_local() -> Result<Vec<RunRecord>> {
+        let path = Self::local_path();
+        if !fs::try_exists(&path).await? {
+            return Ok(Vec::new());
+        }
+        let raw_jsonl = fs::read_to_string(path).await?;
+        raw_jsonl
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).context("Failed to deserialize run history"))
+            .collect()
+    }
+
+    pub async fn find_local(id: u64) -> Result<RunRecord> {
+        Self::read_local().await?
+            .into_iter()
+            .find(|record| record.id == id)
+            .with_context(|| format!("Run `{}` not found in history", id))
+    }
+
+    /// Assigns the next id to the record and appends it to the history
+    pub async fn append_local(mut record: RunRecord) -> Result<RunRecord> {
+        record.id = Self::read_local().await?.last().map(|last| last.id + 1).unwrap_or(1);
+
+        let path = Self::local_path();
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path).await?;
+        let raw_json = serde_json::to_string(&record)?;
+        file.write_all(format!("{}\n", raw_json).as_bytes()).await?;
+        Ok(record)
+    }
+}
+
+/// Template variable values shared by the whole project
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VarsConfig {
+    pub vars: HashMap<String, String>,
+}
+
+impl VarsConfig {
+
+    const CONFIG_FILE: &str = "brichka/vars.json";
+
+    fn local_path() -> String {
+        let cwd = crate::CONTEXT.get().unwrap().cwd.to_owned();
+        format!("{}/.{}", cwd, Self::CONFIG_FILE)
+    }
+
+    async fn read(path: String) -> Result<VarsConfig> {
+        let raw_json = fs::read_to_string(path).await?;
+        serde_json::from_str(&raw_json).context("Failed to deserialize vars config")
+    }
+
+    /// Returns an empty config if the project does not define any variables
+    pub async fn read_local() -> Result<VarsConfig> {
+        let path = Self::local_path();
+        if !fs::try_exists(&path).await? {
+            return Ok(VarsConfig::default());
+        }
+        Self::read(path).await
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -156,3 +629,63 @@ impl AuthConfig {
         self.write(Self::global_path()?).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Working directory shared by the tests of the config files, each test uses its own files in it
+    fn test_cwd() -> String {
+        crate::CONTEXT.get_or_init(|| {
+            let cwd = env::temp_dir().join("brichka-tests").join(format!("config-{}", std::process::id()));
+            std::fs::create_dir_all(&cwd).unwrap();
+            crate::BrichkaContext { debug: false, cwd: cwd.to_string_lossy().to_string(), progress: Default::default() }
+        }).cwd.to_owned()
+    }
+
+    #[tokio::test]
+    async fn concurrent_updates_keep_every_created_context() {
+        test_cwd();
+        let _ = std::fs::remove_file(CreatedContextsConfig::local_path());
+
+        let mut adds = tokio::task::JoinSet::new();
+        for id in 0..20 {
+            adds.spawn(CreatedContextsConfig::add(CreatedContext::new("0101-abc".to_string(), id.to_string())));
+        }
+        while let Some(result) = adds.join_next().await {
+            result.unwrap().unwrap();
+        }
+        CreatedContextsConfig::remove("0101-abc", "7").await.unwrap();
+
+        let mut ids = CreatedContextsConfig::read_local().await.unwrap().contexts.into_iter().map(|c| c.id).collect::<Vec<String>>();
+        ids.sort_by_key(|id| id.parse::<u32>().unwrap());
+        assert_eq!(ids, (0..20).filter(|id| *id != 7).map(|id| id.to_string()).collect::<Vec<String>>());
+
+        let legacy = serde_json::from_str::<CreatedContext>(r#"{"cluster_id":"0101-abc","id":"1"}"#).unwrap();
+        assert_eq!((legacy.created_at, legacy.pid), (0, None));
+    }
+
+    #[tokio::test]
+    async fn migrates_the_legacy_default_context() {
+        let cwd = test_cwd();
+        let _ = std::fs::remove_dir_all(ContextConfig::local_dir());
+        std::fs::create_dir_all(format!("{}/.brichka", cwd)).unwrap();
+        std::fs::write(ContextConfig::legacy_path(), r#"{"id":"1234","cluster_id":"0101-abc"}"#).unwrap();
+
+        let legacy = ContextConfig::read_local(ContextConfig::DEFAULT_NAME).await.unwrap();
+        assert_eq!((legacy.name.as_str(), legacy.id.as_str(), legacy.cluster_id.as_deref()), ("default", "1234", Some("0101-abc")));
+
+        let listed = ContextConfig::list_local().await.unwrap();
+        assert_eq!(listed.iter().map(|c| (c.name.as_str(), c.id.as_str())).collect::<Vec<(&str, &str)>>(), vec![("default", "1234")]);
+
+        let mut moved = legacy;
+        moved.sent_files.push("utils.sc".to_string());
+        moved.write_local().await.unwrap();
+        assert!(!std::path::Path::new(&ContextConfig::legacy_path()).exists());
+        assert!(std::path::Path::new(&ContextConfig::local_path(ContextConfig::DEFAULT_NAME)).exists());
+
+        let read = ContextConfig::read_local(ContextConfig::DEFAULT_NAME).await.unwrap();
+        assert_eq!((read.id.as_str(), read.sent_files.as_slice()), ("1234", ["utils.sc".to_string()].as_slice()));
+        assert_eq!(ContextConfig::list_local().await.unwrap().len(), 1);
+    }
+}
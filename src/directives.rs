@@ -0,0 +1,386 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{language::Language, variables::Declaration};
+
+const DIRECTIVE_PREFIX: &str = "brichka:";
+/// Comment prefix of directives written before they followed the comment syntax of the language, still accepted on their own line
+const LEGACY_COMMENT_PREFIX: &str = "//";
+const USING_DIRECTIVE_PREFIX: &str = "//>";
+
+#[derive(Debug, PartialEq)]
+enum Directive<'a> {
+    /// Start of a block that is not sent to the cluster
+    Exclude,
+    /// End of an excluded block
+    Include,
+    /// Excludes the line that follows the directive
+    ExcludeNextLine,
+    /// Trailing marker that excludes the line it is placed on
+    ExcludeLine,
+    /// Inlines the content of another file
+    IncludeFile(&'a str),
+    /// Declares a template variable, optionally with a default value
+    Var(&'a str),
+    Unknown(&'a str),
+}
+
+impl<'a> Directive<'a> {
+    fn parse(body: &'a str) -> Directive<'a> {
+        let (name, argument) = body.trim().split_once(char::is_whitespace).unwrap_or((body.trim(), ""));
+        match (name, argument.trim()) {
+            ("exclude", "") => Directive::Exclude,
+            ("include", "") => Directive::Include,
+            ("include", path) => Directive::IncludeFile(path),
+            ("var", declaration) if !declaration.is_empty() => Directive::Var(declaration),
+            ("exclude-next-line", "") => Directive::ExcludeNextLine,
+            ("exclude-line", "") => Directive::ExcludeLine,
+            _ => Directive::Unknown(body.trim()),
+        }
+    }
+}
+
+/// Parses a comment of the form `<comment prefix> brichka: <directive>`
+fn parse_comment<'a>(comment: &'a str, comment_prefix: &str) -> Option<Directive<'a>> {
+    let body = comment
+        .strip_prefix(comment_prefix)?
+        .trim_start()
+        .strip_prefix(DIRECTIVE_PREFIX)?;
+    Some(Directive::parse(body))
+}
+
+/// A directive occupying the whole line, in the comment syntax of the language or as a `//` comment
+fn parse_line_directive(line: &str, language: Language) -> Option<Directive<'_>> {
+    parse_comment(line.trim(), language.comment_prefix())
+        .or_else(|| parse_comment(line.trim(), LEGACY_COMMENT_PREFIX))
+}
+
+/// A directive placed in a comment after the code on the same line
+fn parse_trailing_directive(line: &str, language: Language) -> Option<Directive<'_>> {
+    let prefix = language.comment_prefix();
+    line.match_indices(prefix)
+        .filter(|(index, _)| !line[..*index].trim().is_empty())
+        .find_map(|(index, _)| parse_comment(line[index..].trim_end(), prefix))
+}
+
+/// Paths referenced by a Scala `//> using file` or `//> using files` directive
+fn parse_using_files(line: &str) -> Option<Vec<&str>> {
+    let mut words = line.trim().strip_prefix(USING_DIRECTIVE_PREFIX)?.split_whitespace();
+    if words.next()? != "using" || !matches!(words.next()?, "file" | "files") {
+        return None;
+    }
+    Some(words.map(|path| path.trim_matches('"')).collect())
+}
+
+/// Dependencies declared by a Scala `//> using dep` or `//> using deps` directive
+fn parse_using_deps(line: &str) -> Option<Vec<&str>> {
+    let mut words = line.trim().strip_prefix(USING_DIRECTIVE_PREFIX)?.split_whitespace();
+    if words.next()? != "using" || !matches!(words.next()?, "dep" | "deps") {
+        return None;
+    }
+    Some(words.map(|dep| dep.trim_matches('"')).collect())
+}
+
+fn canonicalize(base_dir: &Path, path: &str) -> Result<PathBuf> {
+    let joined = base_dir.join(path);
+    joined.canonicalize().with_context(|| format!("Failed to resolve included file `{}`", joined.display()))
+}
+
+/// Code that is ready to be sent to the cluster
+pub struct Cell {
+    pub code: String,
+    /// Files inlined because of a `//> using file` directive
+    pub using_files: Vec<String>,
+    /// Variables declared with `brichka: var`
+    pub variables: Vec<Declaration>,
+}
+
+struct Preprocessor<'a> {
+    /// `None` if `//> using file` directives are ignored, otherwise the files that were already sent to the execution context
+    sent_files: Option<&'a [String]>,
+    /// Files that are currently being expanded, used to detect cycles
+    stack: Vec<PathBuf>,
+    /// Every file inlined so far
+    inlined: Vec<PathBuf>,
+    using_files: Vec<String>,
+    variables: Vec<Declaration>,
+}
+
+impl Preprocessor<'_> {
+    fn base_dir(&self) -> PathBuf {
+        match self.stack.last().and_then(|path| path.parent()) {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::from(&crate::CONTEXT.get().unwrap().cwd),
+        }
+    }
+
+    fn expand_file(&mut self, path: PathBuf, fallback_language: Language) -> Result<Vec<String>> {
+        if self.stack.contains(&path) {
+            let chain = self.stack.iter()
+                .chain(std::iter::once(&path))
+                .map(|p| p.display().to_string())
+                .collect::<Vec<String>>()
+                .join(" -> ");
+            anyhow::bail!("Include cycle detected: {}", chain);
+        }
+
+        let source = std::fs::read_to_string(&path).with_context(|| format!("Failed to read included file `{}`", path.display()))?;
+        let language = Language::from_extension(&path).unwrap_or(fallback_language);
+
+        self.inlined.push(path.to_owned());
+        self.stack.push(path);
+        let lines = self.process(&source, language);
+        self.stack.pop();
+        lines
+    }
+
+    fn expand_using_files(&mut self, paths: Vec<&str>, language: Language) -> Result<Vec<String>> {
+        let mut result = Vec::new();
+        for path in paths {
+            let path = canonicalize(&self.base_dir(), path)?;
+            let key = path.to_string_lossy().to_string();
+            let already_sent = self.sent_files.is_some_and(|sent| sent.contains(&key));
+            if already_sent || self.inlined.contains(&path) || self.stack.contains(&path) {
+                continue;
+            }
+            let inlined_before = self.inlined.len();
+            result.extend(self.expand_file(path, language)?);
+            // Files included by a using file are defined in the context as well
+            let inlined_files = self.inlined[inlined_before..].iter().map(|p| p.to_string_lossy().to_string());
+            self.using_files.extend(inlined_files);
+        }
+        Ok(result)
+    }
+
+    fn process(&mut self, source: &str, language: Language) -> Result<Vec<String>> {
+        let mut result = Vec::new();
+        let mut exclude_start: Option<usize> = None;
+        let mut exclude_next_line = false;
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+
+            if let Some(directive) = parse_line_directive(line, language) {
+                match directive {
+                    Directive::Exclude => {
+                        if exclude_start.is_none() {
+                            exclude_start = Some(line_number);
+                        }
+                    },
+                    Directive::Include => exclude_start = None,
+                    Directive::ExcludeNextLine => exclude_next_line = true,
+                    Directive::ExcludeLine => {},
+                    Directive::IncludeFile(path) => {
+                        if exclude_start.is_none() && !exclude_next_line {
+                            let path = canonicalize(&self.base_dir(), path)?;
+                            result.extend(self.expand_file(path, language)?);
+                        }
+                        exclude_next_line = false;
+                    },
+                    Directive::Var(declaration) => {
+                        if exclude_start.is_none() && !exclude_next_line {
+                            self.variables.push(crate::variables::parse_declaration(declaration)?);
+                        }
+                        exclude_next_line = false;
+                    },
+                    Directive::Unknown(name) => {
+                        eprintln!("Warning: unknown directive `{}` on line {}", name, line_number);
+                    }
+                }
+                continue;
+            }
+
+            if exclude_next_line {
+                exclude_next_line = false;
+                continue;
+            }
+
+            if exclude_start.is_some() {
+                continue;
+            }
+
+            if parse_trailing_directive(line, language) == Some(Directive::ExcludeLine) {
+                continue;
+            }
+
+            if self.sent_files.is_some()
+                && language == Language::Scala
+                && let Some(paths) = parse_using_files(line)
+            {
+                result.extend(self.expand_using_files(paths, language)?);
+                continue;
+            }
+
+            result.push(line.to_string());
+        }
+
+        if let Some(line_number) = exclude_start {
+            eprintln!(
+                "Warning: `{} brichka: exclude` on line {} is never closed with `{} brichka: include`, the rest of the cell is excluded",
+                language.comment_prefix(),
+                line_number,
+                language.comment_prefix()
+            );
+        }
+
+        Ok(result)
+    }
+}
+
+/// Removes the code marked with `brichka:` directives written in the comment syntax of the given language and inlines included files.
+/// `path` is the file the code was read from, relative includes are resolved against its directory or against the working directory if it is not set.
+/// If `sent_files` is set `//> using file` directives are honoured and files that were already sent to the execution context are skipped
+pub fn preprocess(source: &str, language: Language, path: Option<&Path>, sent_files: Option<&[String]>) -> Result<Cell> {
+    let mut preprocessor = Preprocessor {
+        sent_files,
+        stack: Vec::new(),
+        inlined: Vec::new(),
+        using_files: Vec::new(),
+        variables: Vec::new(),
+    };
+
+    if let Some(path) = path {
+        let path = path.canonicalize().with_context(|| format!("Failed to resolve `{}`", path.display()))?;
+        preprocessor.stack.push(path);
+    }
+
+    let lines = preprocessor.process(source, language)?;
+
+    Ok(Cell {
+        code: lines.join("\n"),
+        using_files: preprocessor.using_files,
+        variables: preprocessor.variables,
+    })
+}
+
+/// Dependencies declared with `//> using dep` in the Scala file and the files it includes, skipping excluded code
+pub fn using_deps(path: &Path) -> Result<Vec<String>> {
+    let source = std::fs::read_to_string(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+    let cell = preprocess(&source, Language::Scala, Some(path), Some(&[]))?;
+    let deps = cell.code
+        .lines()
+        .filter_map(parse_using_deps)
+        .flatten()
+        .map(str::to_string)
+        .collect();
+    Ok(deps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess_code(source: &str, language: Language) -> String {
+        preprocess(source, language, None, None).unwrap().code
+    }
+
+    #[test]
+    fn parses_directives_in_the_comment_syntax_of_the_language() {
+        assert_eq!(parse_line_directive("-- brichka: exclude", Language::Sql), Some(Directive::Exclude));
+        assert_eq!(parse_line_directive("  #brichka: include", Language::Python), Some(Directive::Include));
+        assert_eq!(parse_line_directive("// brichka: exclude-next-line", Language::Scala), Some(Directive::ExcludeNextLine));
+        assert_eq!(parse_line_directive("# brichka: exclude", Language::Sql), None);
+        assert_eq!(parse_line_directive("-- brichka: frobnicate", Language::Sql), Some(Directive::Unknown("frobnicate")));
+    }
+
+    #[test]
+    fn accepts_legacy_slash_comments_on_their_own_line() {
+        assert_eq!(parse_line_directive("// brichka: exclude", Language::Sql), Some(Directive::Exclude));
+        assert_eq!(parse_line_directive("// brichka: include", Language::Python), Some(Directive::Include));
+        assert_eq!(parse_trailing_directive("x = 1 // brichka: exclude-line", Language::Python), None);
+    }
+
+    #[test]
+    fn parses_trailing_directives() {
+        assert_eq!(parse_trailing_directive("df.show()  # brichka: exclude-line", Language::Python), Some(Directive::ExcludeLine));
+        assert_eq!(parse_trailing_directive("select 1 -- brichka: exclude-line", Language::Sql), Some(Directive::ExcludeLine));
+        assert_eq!(parse_trailing_directive("-- brichka: exclude-line", Language::Sql), None);
+    }
+
+    #[test]
+    fn removes_excluded_code() {
+        let source = "// brichka: exclude\nval spark = ???\n// brichka: include\n// brichka: exclude-next-line\nval x = 1\nval y = 2 // brichka: exclude-line\nspark.range(1)";
+        assert_eq!(preprocess_code(source, Language::Scala), "spark.range(1)");
+
+        let source = "-- brichka: exclude\nuse dev;\n-- brichka: include\nselect 1";
+        assert_eq!(preprocess_code(source, Language::Sql), "select 1");
+
+        let source = "// brichka: exclude\nimport fake\n// brichka: include\nprint(1)";
+        assert_eq!(preprocess_code(source, Language::Python), "print(1)");
+    }
+
+    #[test]
+    fn collects_variable_declarations() {
+        let cell = preprocess("-- brichka: var env=dev\n-- brichka: var date\nselect 1", Language::Sql, None, None).unwrap();
+        let declared = cell.variables.iter().map(|d| (d.name.as_str(), d.default.as_deref())).collect::<Vec<(&str, Option<&str>)>>();
+        assert_eq!(declared, vec![("env", Some("dev")), ("date", None)]);
+        assert!(preprocess("-- brichka: var 1date", Language::Sql, None, None).is_err());
+    }
+
+    #[test]
+    fn parses_using_directives() {
+        assert_eq!(parse_using_files("//> using file fst.sc"), Some(vec!["fst.sc"]));
+        assert_eq!(parse_using_files("//> using files \"a.sc\" b.sc"), Some(vec!["a.sc", "b.sc"]));
+        assert_eq!(parse_using_files("//> using dep com.lihaoyi::os-lib:0.9.1"), None);
+        assert_eq!(parse_using_deps("//> using dep com.lihaoyi::os-lib:0.9.1"), Some(vec!["com.lihaoyi::os-lib:0.9.1"]));
+        assert_eq!(parse_using_deps("// using dep foo:bar:1"), None);
+    }
+
+    /// Creates an empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("brichka-tests").join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn inlines_included_files() {
+        let dir = test_dir("include");
+        std::fs::write(dir.join("common.sql"), "-- brichka: exclude\nuse dev;\n-- brichka: include\ncreate temp view v as select 1;").unwrap();
+        std::fs::write(dir.join("main.sql"), "-- brichka: include common.sql\nselect * from v").unwrap();
+
+        let main = dir.join("main.sql");
+        let source = std::fs::read_to_string(&main).unwrap();
+        let cell = preprocess(&source, Language::Sql, Some(&main), None).unwrap();
+        assert_eq!(cell.code, "create temp view v as select 1;\nselect * from v");
+    }
+
+    #[test]
+    fn detects_include_cycles() {
+        let dir = test_dir("cycle");
+        std::fs::write(dir.join("a.sql"), "-- brichka: include b.sql").unwrap();
+        std::fs::write(dir.join("b.sql"), "-- brichka: include a.sql").unwrap();
+
+        let a = dir.join("a.sql");
+        let error = preprocess("-- brichka: include b.sql", Language::Sql, Some(&a), None).err().unwrap();
+        assert!(error.to_string().starts_with("Include cycle detected"));
+    }
+
+    #[test]
+    fn inlines_using_files_once() {
+        let dir = test_dir("using");
+        std::fs::write(dir.join("fst.sc"), "val x = 1").unwrap();
+        let snd = dir.join("snd.sc");
+        std::fs::write(&snd, "//> using file fst.sc\nval y = x").unwrap();
+
+        let cell = preprocess("//> using file fst.sc\nval y = x", Language::Scala, Some(&snd), Some(&[])).unwrap();
+        assert_eq!(cell.code, "val x = 1\nval y = x");
+        assert_eq!(cell.using_files, vec![dir.join("fst.sc").canonicalize().unwrap().to_string_lossy().to_string()]);
+
+        let cell = preprocess("//> using file fst.sc\nval y = x", Language::Scala, Some(&snd), Some(&cell.using_files)).unwrap();
+        assert_eq!(cell.code, "val y = x");
+
+        let cell = preprocess("//> using file fst.sc\nval y = x", Language::Scala, Some(&snd), None).unwrap();
+        assert_eq!(cell.code, "//> using file fst.sc\nval y = x");
+    }
+
+    #[test]
+    fn reads_using_deps_outside_excluded_code() {
+        let dir = test_dir("deps");
+        let path = dir.join("main.sc");
+        std::fs::write(&path, "//> using dep com.lihaoyi::os-lib:0.9.1\n// brichka: exclude\n//> using dep org.apache.spark::spark-sql:3.5.0\n// brichka: include").unwrap();
+        assert_eq!(using_deps(&path).unwrap(), vec!["com.lihaoyi::os-lib:0.9.1"]);
+    }
+}
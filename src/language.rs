@@ -0,0 +1,95 @@
+use std::{fmt, path::Path};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Sql,
+    Scala,
+    Python,
+    R,
+}
+
+impl Language {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Language::Sql => "sql",
+            Language::Scala => "scala",
+            Language::Python => "python",
+            Language::R => "r",
+        }
+    }
+
+    /// Prefix of a single-line comment
+    pub fn comment_prefix(&self) -> &'static str {
+        match self {
+            Language::Sql => "--",
+            Language::Scala => "//",
+            Language::Python | Language::R => "#",
+        }
+    }
+
+    pub fn from_extension(path: &Path) -> Option<Language> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "sql" => Some(Language::Sql),
+            "sc" | "scala" => Some(Language::Scala),
+            "py" => Some(Language::Python),
+            "r" => Some(Language::R),
+            _ => None,
+        }
+    }
+
+    fn from_magic(magic: &str) -> Option<Language> {
+        match magic {
+            "%sql" => Some(Language::Sql),
+            "%scala" => Some(Language::Scala),
+            "%python" => Some(Language::Python),
+            "%r" => Some(Language::R),
+            _ => None,
+        }
+    }
+
+    /// Detects a Databricks magic command (`%sql`, `%python`, `%scala` or `%r`) on the first non-empty line of a cell.
+    /// Returns the language it selects and the cell without the magic command
+    pub fn split_magic(command: &str) -> Option<(Language, String)> {
+        let mut lines = command.lines().skip_while(|line| line.trim().is_empty());
+        let first_line = lines.next()?.trim();
+
+        let (magic, rest) = first_line.split_once(char::is_whitespace).unwrap_or((first_line, ""));
+        let language = Self::from_magic(magic)?;
+
+        let remaining = std::iter::once(rest.trim_start()).chain(lines).skip_while(|line| line.trim().is_empty());
+        Some((language, remaining.collect::<Vec<&str>>().join("\n")))
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infers_language_from_extension() {
+        assert_eq!(Language::from_extension(Path::new("query.SQL")), Some(Language::Sql));
+        assert_eq!(Language::from_extension(Path::new("script.sc")), Some(Language::Scala));
+        assert_eq!(Language::from_extension(Path::new("job.py")), Some(Language::Python));
+        assert_eq!(Language::from_extension(Path::new("notes.txt")), None);
+        assert_eq!(Language::from_extension(Path::new("Makefile")), None);
+    }
+
+    #[test]
+    fn splits_magic_command() {
+        assert_eq!(Language::split_magic("\n%python\nprint(1)\n"), Some((Language::Python, "print(1)".to_string())));
+        assert_eq!(Language::split_magic("%sql select 1"), Some((Language::Sql, "select 1".to_string())));
+        assert_eq!(Language::split_magic("%md # Title"), None);
+        assert_eq!(Language::split_magic("select 1 % 2"), None);
+    }
+}
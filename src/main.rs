@@ -2,18 +2,23 @@ mod client;
 mod cli;
 mod commands;
 mod config;
+mod directives;
+mod language;
+mod progress;
+mod variables;
 
 use std::sync::OnceLock;
 
 use anyhow::{Result, Context};
 use clap::Parser;
 
-use crate::cli::{Cli, ClusterCommands, Commands, ConfigCommands, StatusCommands};
+use crate::cli::{Cli, ClusterCommands, Commands, ConfigCommands, ContextCommands, DepsCommands, HistoryCommands, LibraryCommands, StatusCommands, WarehouseCommands};
 
 #[derive(Debug)]
 pub struct BrichkaContext {
    pub debug: bool,
-   pub cwd: String
+   pub cwd: String,
+   pub progress: progress::ProgressFormat,
 }
 
 pub static CONTEXT: OnceLock<BrichkaContext> = OnceLock::new();
@@ -23,23 +28,57 @@ async fn run(cli: &Cli) -> Result<()> {
 
     match cli.command.to_owned() {
         Commands::Cluster { command } => match command {
-            ClusterCommands::List => commands::cluster::list().await?,
-            ClusterCommands::Start => commands::cluster::start().await?,
-            ClusterCommands::Status => commands::cluster::status().await?
+            ClusterCommands::List(filter) => commands::cluster::list(filter).await?,
+            ClusterCommands::Start { timeout } => commands::cluster::start(timeout).await?,
+            ClusterCommands::Create { from, name, timeout } => commands::cluster::create(from, name, timeout).await?,
+            ClusterCommands::Destroy { force } => commands::cluster::destroy(force).await?,
+            ClusterCommands::Stop { wait } => commands::cluster::stop(wait).await?,
+            ClusterCommands::Restart { wait } => commands::cluster::restart(wait).await?,
+            ClusterCommands::Status => commands::cluster::status().await?,
+            ClusterCommands::Events { since, until, event_types, limit } => commands::cluster::events(since, until, event_types, limit).await?,
+            ClusterCommands::Libraries { command } => match command {
+                LibraryCommands::List => commands::library::list().await?,
+                LibraryCommands::Install { libraries, repo, wait } => commands::library::install(libraries, repo, wait).await?,
+                LibraryCommands::Uninstall { libraries, repo } => commands::library::uninstall(libraries, repo).await?,
+            },
+            ClusterCommands::Info { pretty } => commands::cluster::info(pretty).await?,
+            ClusterCommands::Logs { file, follow, lines } => commands::logs::logs(file, follow, lines).await?
+        },
+        Commands::Warehouse { command } => match command {
+            WarehouseCommands::List => commands::warehouse::list().await?,
+            WarehouseCommands::Start { wait } => commands::warehouse::start(wait).await?,
+            WarehouseCommands::Stop { wait } => commands::warehouse::stop(wait).await?,
+            WarehouseCommands::Status => commands::warehouse::status().await?
         },
         Commands::Config { command, global } => match command {
-            ConfigCommands::Cluster => commands::config::select_cluster(global).await?,
+            ConfigCommands::Cluster { id, filter, attachable_only } => commands::config::select_cluster(id, filter, attachable_only, global).await?,
+            ConfigCommands::Warehouse => commands::config::select_warehouse(global).await?,
             ConfigCommands::Auth { command } => match command{
                 cli::AuthConfigCommands::Token { value, host } => crate::commands::config::configure_token_auth(value, host).await?,
                 cli::AuthConfigCommands::Cli { executable: path, profile } => crate::commands::config::configure_cli_auth(path, profile).await?,
             },
         },
-        Commands::Init => commands::init::init().await?,
+        Commands::Init { name, language } => commands::init::init(name, language).await?,
+        Commands::Context { command } => match command {
+            ContextCommands::List => commands::context::list().await?,
+            ContextCommands::Destroy { name } => commands::context::destroy(name).await?,
+            ContextCommands::Gc => commands::context::gc().await?,
+            ContextCommands::Inspect { name, language } => commands::context::inspect(name, language).await?,
+            ContextCommands::Replay { name, start } => commands::context::replay(name, start).await?,
+        },
         Commands::Status { command } => match command {
-            StatusCommands::Context => commands::status::context().await?,
+            StatusCommands::Context { name } => commands::status::context(name).await?,
             StatusCommands::Cluster => commands::status::cluster().await?
         },
-        Commands::Run { command, language, init, start } => commands::run::run(command.into_inner(), language, init, start).await?,
+        Commands::Run(args) => commands::run::run(args).await?,
+        Commands::History { command } => match command {
+            HistoryCommands::List { limit } => commands::history::list(limit).await?,
+            HistoryCommands::Show { id } => commands::history::show(id).await?,
+            HistoryCommands::Rerun { id, start } => commands::history::rerun(id, start).await?,
+        },
+        Commands::Deps { command } => match command {
+            DepsCommands::Sync { files, dry_run, wait } => commands::deps::sync(files, dry_run, wait).await?,
+        },
         Commands::Lsp => commands::lsp::start().await?,
         Commands::Version => println!("{}", env!("CARGO_PKG_VERSION"))
     };
@@ -63,15 +102,16 @@ async fn main() -> Result<()> {
 
     CONTEXT.set(BrichkaContext{
         debug: cli.debug,
-        cwd: cwd
+        cwd,
+        progress: cli.progress,
     }).unwrap();
 
     let result = run(&cli).await;
 
     if let Err(e) = result {
-        eprintln!("{}", e.to_string());
+        eprintln!("{}", e);
         if CONTEXT.get().unwrap().debug {
-            eprintln!("{}", e.backtrace().to_string());
+            eprintln!("{}", e.backtrace());
         }
     }
     
@@ -0,0 +1,113 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// How progress of long running operations is reported on stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProgressFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for editor plugins
+    Json,
+    /// Nothing is reported and the Spark jobs of a command are not polled
+    None,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    /// A cluster or SQL warehouse changed its state
+    State {
+        resource: &'a str,
+        id: &'a str,
+        state: &'a str,
+        elapsed_secs: u64,
+    },
+    /// An event reported by the cluster while waiting for it, e.g. nodes being added
+    ClusterEvent {
+        id: &'a str,
+        #[serde(rename = "type")]
+        tpe: &'a str,
+        message: String,
+        elapsed_secs: u64,
+    },
+    /// A result chunk of a SQL statement was written to the result file
+    Download {
+        chunk: usize,
+        total_chunks: usize,
+        rows: usize,
+        bytes: u64,
+    },
+    /// A library installed on the cluster changed its status
+    Library {
+        library: &'a str,
+        status: &'a str,
+        elapsed_secs: u64,
+    },
+    /// A recorded cell is executed again in a new execution context
+    Replay {
+        cell: usize,
+        total_cells: usize,
+    },
+    /// A command is still running, its Spark jobs can be followed in the Spark UI
+    SparkUi {
+        cluster_id: &'a str,
+        command_id: &'a str,
+        url: String,
+    },
+    /// A Spark job of the running command started or finished more tasks
+    SparkJob {
+        job_id: u64,
+        name: &'a str,
+        status: &'a str,
+        completed_tasks: u64,
+        total_tasks: u64,
+        active_stages: u64,
+        elapsed_secs: u64,
+    },
+}
+
+fn format_elapsed(seconds: u64) -> String {
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+fn format_text(event: &ProgressEvent) -> String {
+    match event {
+        ProgressEvent::State { state, elapsed_secs, .. } => {
+            format!("{} {} ({})", crate::commands::render_state(state), state, format_elapsed(*elapsed_secs))
+        },
+        ProgressEvent::ClusterEvent { message, elapsed_secs, .. } => format!("   {} ({})", message, format_elapsed(*elapsed_secs)),
+        ProgressEvent::Download { chunk, total_chunks, rows, bytes } => {
+            format!("Downloaded chunk {}/{} ({} rows, {:.1} MB)", chunk, total_chunks, rows, *bytes as f64 / 1_000_000.0)
+        },
+        ProgressEvent::Library { library, status, elapsed_secs } => format!("{}: {} ({})", library, status, format_elapsed(*elapsed_secs)),
+        ProgressEvent::Replay { cell, total_cells } => format!("Replaying cell {}/{}", cell, total_cells),
+        ProgressEvent::SparkUi { url, .. } => format!("Spark UI: {}", url),
+        ProgressEvent::SparkJob { job_id, name, completed_tasks, total_tasks, active_stages, elapsed_secs, .. } => format!(
+            "Spark job {} ({}): {}/{} tasks, {} active stages ({})",
+            job_id, name, completed_tasks, total_tasks, active_stages, format_elapsed(*elapsed_secs)
+        ),
+    }
+}
+
+/// Whether progress is reported, i.e. `--progress none` is not set
+pub fn is_enabled() -> bool {
+    crate::CONTEXT.get().map(|context| context.progress).unwrap_or_default() != ProgressFormat::None
+}
+
+/// Prints the event on stderr in the format selected with `--progress`
+pub fn report(event: ProgressEvent) {
+    let format = crate::CONTEXT.get().map(|context| context.progress).unwrap_or_default();
+    match format {
+        ProgressFormat::Text => eprintln!("{}", format_text(&event)),
+        ProgressFormat::Json => match serde_json::to_string(&event) {
+            Ok(raw_json) => eprintln!("{}", raw_json),
+            Err(e) => eprintln!("Warning: failed to serialize progress event: {}", e),
+        },
+        ProgressFormat::None => {},
+    }
+}
@@ -0,0 +1,350 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use anyhow::Result;
+
+use crate::language::Language;
+
+/// Parses a `name=value` assignment
+pub fn parse_assignment(assignment: &str) -> Result<(String, String)> {
+    let (name, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Expected `name=value`, got `{}`", assignment))?;
+    let name = name.trim();
+    if !is_identifier(name) {
+        anyhow::bail!("Invalid variable name `{}`", name);
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Type of a declared variable, it decides how the value is rendered as a SQL parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarType {
+    String,
+    Int,
+    Number,
+}
+
+impl VarType {
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            VarType::String => true,
+            VarType::Int => value.parse::<i64>().is_ok(),
+            VarType::Number => is_number(value),
+        }
+    }
+}
+
+impl fmt::Display for VarType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VarType::String => "string",
+            VarType::Int => "int",
+            VarType::Number => "number",
+        })
+    }
+}
+
+/// A variable declared with `brichka: var name[:type][=default]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declaration {
+    pub name: String,
+    pub tpe: VarType,
+    pub default: Option<String>,
+}
+
+/// Parses `name`, `name=default`, `name:int` or `name:number=default`
+pub fn parse_declaration(declaration: &str) -> Result<Declaration> {
+    let (name, default) = match declaration.split_once('=') {
+        Some((name, default)) => (name.trim(), Some(default.trim().to_string())),
+        None => (declaration.trim(), None),
+    };
+    let (name, tpe) = match name.split_once(':') {
+        Some((name, "int")) => (name, VarType::Int),
+        Some((name, "number")) => (name, VarType::Number),
+        Some((_, tpe)) => anyhow::bail!("Unknown variable type `{}`, expected `int` or `number`", tpe),
+        None => (name, VarType::String),
+    };
+    if !is_identifier(name) {
+        anyhow::bail!("Invalid variable declaration `{}`", declaration);
+    }
+    Ok(Declaration { name: name.to_string(), tpe, default })
+}
+
+/// Resolved variable values and the types declared for them
+#[derive(Debug, Default, PartialEq)]
+pub struct Vars {
+    pub values: HashMap<String, String>,
+    /// Variables declared as `int` or `number`, rendered as SQL parameters without quotes
+    numbers: HashSet<String>,
+    /// Variables declared by the cell or passed on the command line
+    explicit: HashSet<String>,
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_identifier_start) && chars.all(is_identifier_char)
+}
+
+fn is_number(value: &str) -> bool {
+    !value.is_empty()
+        && value.trim_start_matches('-').chars().all(|c| c.is_ascii_digit() || c == '.')
+        && value.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+/// Renders a value as an escaped SQL string literal
+pub fn sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Replaces `${name}` with the raw value of the variables in `vars`, other names are left untouched
+fn substitute_templates(code: &str, vars: &HashMap<&str, &str>) -> String {
+    let mut result = String::with_capacity(code.len());
+    let mut rest = code;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find('}') {
+            Some(end) if vars.contains_key(after[..end].trim()) => {
+                result.push_str(vars[after[..end].trim()]);
+                rest = &after[end + 1..];
+            },
+            _ => {
+                result.push_str("${");
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Replaces `:name` parameters with SQL literals, quoted unless the variable is declared as a number.
+/// Strings, quoted identifiers, comments and `::` casts are skipped
+fn substitute_sql_parameters(code: &str, vars: &Vars) -> String {
+    let chars: Vec<char> = code.chars().collect();
+    let mut result = String::with_capacity(code.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' | '"' | '`' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && chars[i] != c {
+                    if chars[i] == '\\' && c != '`' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i = (i + 1).min(chars.len());
+                result.extend(&chars[start..i]);
+            },
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                let start = i;
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                result.extend(&chars[start..i]);
+            },
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let start = i;
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i = (i + 2).min(chars.len());
+                result.extend(&chars[start..i]);
+            },
+            ':' if (i == 0 || chars[i - 1] != ':') && chars.get(i + 1).is_some_and(|c| is_identifier_start(*c)) => {
+                let start = i + 1;
+                let mut end = start;
+                while end < chars.len() && is_identifier_char(chars[end]) {
+                    end += 1;
+                }
+                let name: String = chars[start..end].iter().collect();
+                match vars.values.get(&name) {
+                    Some(value) if vars.numbers.contains(&name) => {
+                        result.push_str(value);
+                        i = end;
+                    },
+                    Some(value) => {
+                        result.push_str(&sql_literal(value));
+                        i = end;
+                    },
+                    None => {
+                        result.push(':');
+                        i += 1;
+                    }
+                }
+            },
+            _ => {
+                result.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    result
+}
+
+/// Substitutes `${name}` templates in any language and `:name` parameters in SQL.
+/// Outside SQL only variables declared by the cell or passed with `--var` are substituted, so e.g. a Scala
+/// `s"${env}"` referring to a local value keeps working when the project config defines `env`
+pub fn substitute(code: &str, language: Language, vars: &Vars) -> String {
+    let templates = vars.values.iter()
+        .filter(|(name, _)| language == Language::Sql || vars.explicit.contains(*name))
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect::<HashMap<&str, &str>>();
+    let code = substitute_templates(code, &templates);
+    if language == Language::Sql {
+        substitute_sql_parameters(&code, vars)
+    } else {
+        code
+    }
+}
+
+/// Merges variable values by priority: command line values, then the project config, then the defaults declared with `brichka: var`.
+/// Fails if a declared variable has no value or a value that doesn't match its type
+pub fn resolve(
+    declared: Vec<Declaration>,
+    config: HashMap<String, String>,
+    cli: Vec<(String, String)>,
+) -> Result<Vars> {
+    let mut values = HashMap::new();
+    let mut missing = Vec::new();
+    let explicit = declared.iter().map(|declaration| declaration.name.to_owned())
+        .chain(cli.iter().map(|(name, _)| name.to_owned()))
+        .collect::<HashSet<String>>();
+
+    for declaration in &declared {
+        match &declaration.default {
+            Some(value) => {
+                values.insert(declaration.name.to_owned(), value.to_owned());
+            },
+            None => missing.push(declaration.name.to_owned()),
+        }
+    }
+    values.extend(config);
+    values.extend(cli);
+
+    missing.retain(|name| !values.contains_key(name));
+    if !missing.is_empty() {
+        anyhow::bail!("No value for variables: {}. Pass them with `--var name=value`", missing.join(", "));
+    }
+
+    let mut numbers = HashSet::new();
+    for declaration in declared.iter().filter(|declaration| declaration.tpe != VarType::String) {
+        let value = &values[&declaration.name];
+        if !declaration.tpe.accepts(value) {
+            anyhow::bail!("Variable `{}` is declared as `{}`, but its value is `{}`", declaration.name, declaration.tpe, value);
+        }
+        numbers.insert(declaration.name.to_owned());
+    }
+
+    Ok(Vars { values, numbers, explicit })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Vars {
+        Vars { values: values(pairs), numbers: HashSet::new(), explicit: pairs.iter().map(|(name, _)| name.to_string()).collect() }
+    }
+
+    fn declaration(name: &str, tpe: VarType, default: Option<&str>) -> Declaration {
+        Declaration { name: name.to_string(), tpe, default: default.map(str::to_string) }
+    }
+
+    #[test]
+    fn parses_assignments() {
+        assert_eq!(parse_assignment(" env =prod=1").unwrap(), ("env".to_string(), "prod=1".to_string()));
+        assert!(parse_assignment("env").is_err());
+        assert!(parse_assignment("1env=prod").is_err());
+    }
+
+    #[test]
+    fn parses_declarations() {
+        assert_eq!(parse_declaration("env = dev").unwrap(), declaration("env", VarType::String, Some("dev")));
+        assert_eq!(parse_declaration("limit:int=10").unwrap(), declaration("limit", VarType::Int, Some("10")));
+        assert_eq!(parse_declaration("ratio:number").unwrap(), declaration("ratio", VarType::Number, None));
+        assert!(parse_declaration("limit:long").is_err());
+        assert!(parse_declaration("1date").is_err());
+    }
+
+    #[test]
+    fn renders_sql_literals() {
+        assert_eq!(sql_literal("42"), "'42'");
+        assert_eq!(sql_literal("it's"), "'it\\'s'");
+        assert_eq!(sql_literal(""), "''");
+    }
+
+    #[test]
+    fn quotes_parameters_unless_declared_as_numbers() {
+        let declared = vec![declaration("account", VarType::String, None), declaration("limit", VarType::Int, None)];
+        let vars = resolve(declared, HashMap::new(), vec![("account".to_string(), "00123".to_string()), ("limit".to_string(), "10".to_string())]).unwrap();
+        assert_eq!(substitute("where account = :account limit :limit", Language::Sql, &vars), "where account = '00123' limit 10");
+
+        let declared = vec![declaration("limit", VarType::Int, Some("10; drop table t"))];
+        assert!(resolve(declared, HashMap::new(), Vec::new()).is_err());
+        let declared = vec![declaration("ratio", VarType::Number, Some("0.5"))];
+        assert!(resolve(declared, HashMap::new(), Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn substitutes_templates_in_any_language() {
+        let vars = vars(&[("env", "dev")]);
+        assert_eq!(substitute("val t = \"events_${env}\"; s\"${other}\"", Language::Scala, &vars), "val t = \"events_dev\"; s\"${other}\"");
+        assert_eq!(substitute("print(':env')", Language::Python, &vars), "print(':env')");
+    }
+
+    #[test]
+    fn substitutes_only_explicit_variables_outside_sql() {
+        let declared = vec![declaration("table", VarType::String, None)];
+        let config = values(&[("env", "dev"), ("table", "events")]);
+        let vars = resolve(declared, config, vec![("date".to_string(), "2026-01-01".to_string())]).unwrap();
+        assert_eq!(
+            substitute("val env = \"prod\"; spark.table(\"${table}\").where(s\"env = '${env}' and date = '${date}'\")", Language::Scala, &vars),
+            "val env = \"prod\"; spark.table(\"events\").where(s\"env = '${env}' and date = '2026-01-01'\")"
+        );
+        assert_eq!(substitute("select * from ${table}_${env}", Language::Sql, &vars), "select * from events_dev");
+    }
+
+    #[test]
+    fn substitutes_sql_parameters_outside_strings_and_comments() {
+        let vars = Vars { numbers: HashSet::from(["limit".to_string()]), ..vars(&[("date", "2026-01-01"), ("limit", "10")]) };
+        let code = "select ':date', x::date -- :date\nfrom t /* :limit */ where d = :date limit :limit";
+        let expected = "select ':date', x::date -- :date\nfrom t /* :limit */ where d = '2026-01-01' limit 10";
+        assert_eq!(substitute(code, Language::Sql, &vars), expected);
+        assert_eq!(substitute("select :unknown", Language::Sql, &vars), "select :unknown");
+    }
+
+    #[test]
+    fn resolves_variables_by_priority() {
+        let declared = vec![
+            declaration("env", VarType::String, Some("dev")),
+            declaration("date", VarType::String, None),
+            declaration("limit", VarType::String, Some("5")),
+        ];
+        let config = values(&[("env", "staging"), ("date", "2026-01-01")]);
+        let cli = vec![("env".to_string(), "prod".to_string())];
+        let resolved = resolve(declared, config, cli).unwrap();
+        assert_eq!(resolved.values, values(&[("env", "prod"), ("date", "2026-01-01"), ("limit", "5")]));
+
+        let error = resolve(vec![declaration("date", VarType::String, None)], HashMap::new(), Vec::new()).err().unwrap();
+        assert!(error.to_string().contains("date"));
+    }
+}